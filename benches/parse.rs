@@ -0,0 +1,43 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use lilac::{parse::parse_full, Dialect};
+
+/// Builds a component of roughly `lines` lines with a mix of elements, attributes, long
+/// (partly non-ASCII) text and blocks.
+fn component(lines: usize) -> String {
+    let mut source = String::from("<script>\n    let items = [1, 2, 3];\n</script>\n");
+
+    for i in 0..lines / 8 {
+        source.push_str(&format!(
+            r#"<section id="s{i}" class='card wide' data-index={i}>
+    <h2 title="Section {i}">Übersicht &amp; résumé — section {i} {{items.length}}</h2>
+    <p>Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt ut labore et dolore magna aliqua. 日本語のテキスト。</p>
+    <!-- a comment that the parser has to skip over -->
+    {{#for item in items}}
+        <span aria-label="item">{{item}}</span>
+    {{/for}}
+</section>
+"#
+        ));
+    }
+
+    source
+}
+
+fn parse_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+
+    for lines in [250, 500, 1000, 2000, 4000] {
+        let source = component(lines);
+
+        // Constant throughput across sizes means parsing scales linearly.
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(lines), &source, |b, source| {
+            b.iter(|| parse_full(source, "Root", Dialect::JsLilac).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, parse_benchmark);
+criterion_main!(benches);
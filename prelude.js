@@ -12,12 +12,50 @@ const assert = (condition, message) => {
     }
 }
 
+// Subscriptions remember the component instance they were made in, for its update hooks.
 const subscribe_fn = (subList) => (callback) => {
     const id = ID_COUNTER++;
-    subList.set(id, callback);
+    subList.set(id, { callback, context: __current_context });
     return () => subList.delete(id);
 }
 
+// The component instances updated by the current batch, or null outside of one.
+let __updated_contexts = null;
+
+// Runs a batch of updates, after which the afterUpdate hooks of every instance it touched run.
+const __batch = (run) => {
+    if (__updated_contexts !== null) return run();
+
+    __updated_contexts = new Set();
+
+    try {
+	run();
+    } finally {
+	const contexts = __updated_contexts;
+	__updated_contexts = null;
+	contexts.forEach((context) => context.after_update.forEach((fn) => fn()));
+    }
+}
+
+// An instance's beforeUpdate hooks run right before the first of its subscriptions in a batch.
+const __run_subscription = ({ callback, context }, ...args) => {
+    const instance = context === null ? null : context.instance;
+
+    if (instance !== null && __updated_contexts !== null && !__updated_contexts.has(instance)) {
+	__updated_contexts.add(instance);
+	instance.before_update.forEach((fn) => fn());
+    }
+
+    const boundary = __find_boundary(context);
+    if (boundary === null) return callback(...args);
+
+    try {
+	callback(...args);
+    } catch (error) {
+	boundary.fail(error, context);
+    }
+}
+
 class State {
     __STATE = true;
     #value;
@@ -28,12 +66,12 @@ class State {
     }
 
     run_subscriptions = () => {
-	this.#subscriptions.forEach((sub) => sub(this.#value));
+	this.#subscriptions.forEach((sub) => __run_subscription(sub, this.#value));
     }
 
     set = (setter) => {
 	this.#value = setter(this.#value);
-	this.run_subscriptions();
+	__batch(this.run_subscriptions);
     }
 
     get = () => {
@@ -99,8 +137,10 @@ class LState extends State {
 	const outer_rendering = __currently_rendering;
 	__currently_rendering = key;
 
-	this.run_subscriptions();
-	this.#addSubscriptions.forEach((sub) => sub(item, position));
+	__batch(() => {
+	    this.run_subscriptions();
+	    this.#addSubscriptions.forEach((sub) => __run_subscription(sub, item, position));
+	});
 
 	__currently_rendering = outer_rendering;
     }
@@ -111,8 +151,10 @@ class LState extends State {
 
 	__run_unmounts(key);
 
-	this.run_subscriptions();
-	this.#removeSubscriptions.forEach((sub) => sub(position));
+	__batch(() => {
+	    this.run_subscriptions();
+	    this.#removeSubscriptions.forEach((sub) => __run_subscription(sub, position));
+	});
     }
 
     push = (item) => {
@@ -157,3 +199,273 @@ const lstate = (initialState) => {
 
 const __conditionals_previous_result = {};
 
+
+// Blocks render their nodes between two empty comments rather than into a wrapper element, so
+// they sit directly in their real parent.
+const __anchors = () => [document.createComment(""), document.createComment("")];
+
+const __clear_between = (start, end) => {
+    let node = start.nextSibling;
+
+    while (node !== end) {
+	const next = node.nextSibling;
+	__remove_node(node);
+	node = next;
+    }
+}
+
+// Nodes with outros in them are only removed once those have finished. They stay where they are
+// until then, and are skipped if cleared again.
+const __remove_node = (node) => {
+    if (node.__outroing === true) return;
+
+    const outros = __find_outros(node);
+
+    if (outros.length === 0) {
+	node.remove();
+	return;
+    }
+
+    node.__outroing = true;
+
+    let remaining = outros.length;
+
+    outros.forEach((element) => __play_transition(element, element.__outro, false, () => {
+	remaining--;
+	if (remaining === 0) node.remove();
+    }));
+}
+
+const __find_outros = (node) => {
+    const outros = node.__outro !== undefined ? [node] : [];
+    node.childNodes.forEach((child) => outros.push(...__find_outros(child)));
+    return outros;
+}
+
+// Intros only play for elements added by an update, not ones rendered with the page.
+const __transition = (element, intro, outro) => {
+    if (outro !== null) element.__outro = outro;
+    if (intro !== null && __updated_contexts !== null) __play_transition(element, intro, true);
+}
+
+// Transition functions take the element and the parameters, and return the duration, delay and
+// easing, with a `css(t)` giving the styles at `t` from 0 (not there) to 1 (there) and/or a
+// `tick(t)` called every frame.
+const __play_transition = (element, { fn, params }, intro, done) => {
+    if (element.__stop_transition !== undefined) element.__stop_transition();
+
+    const { delay = 0, duration = 300, easing = (t) => t, css, tick } = fn(element, params);
+    const style = element.style.cssText;
+    const start = performance.now() + delay;
+    let stopped = false;
+
+    const step = (t) => {
+	const eased = easing(t);
+	if (css !== undefined) element.style.cssText = `${style};${css(eased)}`;
+	if (tick !== undefined) tick(eased);
+    }
+
+    const stop = () => {
+	stopped = true;
+	element.__stop_transition = undefined;
+	element.style.cssText = style;
+    }
+
+    const frame = (now) => {
+	if (stopped) return;
+
+	const progress = duration > 0 ? Math.min(Math.max((now - start) / duration, 0), 1) : 1;
+	step(intro ? progress : 1 - progress);
+
+	if (progress < 1) {
+	    requestAnimationFrame(frame);
+	} else {
+	    stop();
+	    if (done !== undefined) done();
+	}
+    }
+
+    element.__stop_transition = stop;
+    step(intro ? 0 : 1);
+    requestAnimationFrame(frame);
+}
+
+const fade = (element, { delay = 0, duration = 400, easing } = {}) => ({
+    delay,
+    duration,
+    easing,
+    css: (t) => `opacity: ${t}`,
+});
+
+const slide = (element, { delay = 0, duration = 400, easing } = {}) => {
+    const height = element.offsetHeight;
+
+    return {
+	delay,
+	duration,
+	easing,
+	css: (t) => `overflow: hidden; height: ${t * height}px`,
+    };
+}
+
+const scale = (element, { delay = 0, duration = 400, easing, start = 0 } = {}) => ({
+    delay,
+    duration,
+    easing,
+    css: (t) => `transform: scale(${start + (1 - start) * t}); opacity: ${t}`,
+});
+
+// Elements with spread attributes apply all of their attributes together, each one a layer in
+// source order, so later ones win on every update and not just the first. A `class` from a spread
+// is added to the element's own classes rather than replacing them.
+const __attribute_layers = (element, count) => {
+    const layers = new Array(count).fill({});
+    let previous = {};
+    let previous_classes = [];
+
+    return (index, values) => {
+        layers[index] = values ?? {};
+        const attributes = Object.assign({}, ...layers);
+
+        for (const key in previous) {
+            if (!(key in attributes) && key !== "class") element.removeAttribute(key);
+        }
+        previous = attributes;
+
+        for (const [key, value] of Object.entries(attributes)) {
+            if (key === "class") continue;
+            if (value === null || value === undefined || value === false) element.removeAttribute(key);
+            else element.setAttribute(key, value === true ? "" : value);
+        }
+
+        const classes = attributes.class ? String(attributes.class).split(" ").filter((c) => c !== "") : [];
+        element.classList.remove(...previous_classes.filter((c) => !classes.includes(c)));
+        element.classList.add(...classes);
+        previous_classes = classes;
+    };
+};
+
+// Inserting into the document attaches the components rendered into `node`, so their mount
+// hooks can run.
+const __insert_before = (node, anchor) => {
+    anchor.parentNode.insertBefore(node, anchor);
+    if (anchor.isConnected) __flush_mounts();
+}
+
+// Every component instance that dispatches events gets its own `dispatch`, calling the handlers
+// it was given.
+const __dispatcher = (handlers) => (name, detail) => {
+    const handler = handlers[name];
+    if (handler !== undefined) handler(detail);
+}
+
+// Contexts follow the component instances, each instance gets one inheriting from the context it
+// was created in. They also hold the instance's lifecycle hooks.
+let __current_context = null;
+
+const __enter_context = (name) => {
+    const context = {
+	name,
+	values: new Map(),
+	parent: __current_context,
+	mounts: [],
+	before_update: [],
+	after_update: [],
+    };
+
+    context.instance = context;
+    __current_context = context;
+}
+
+// Mount hooks of instances that are created but maybe not attached yet. Children finish before
+// their parents, so theirs run first.
+let __pending_mounts = [];
+
+const __exit_context = () => {
+    __pending_mounts.push(...__current_context.mounts);
+    __current_context = __current_context.parent;
+}
+
+const __flush_mounts = () => {
+    const mounts = __pending_mounts;
+    __pending_mounts = [];
+    mounts.forEach((fn) => fn());
+}
+
+// Runs the callback once the component's nodes are in the document. A function it returns is run
+// when the component is unmounted.
+const mount = (callback) => {
+    assert(__current_context !== null, "mount can only be called while a component is created");
+
+    const rendering = __currently_rendering;
+
+    __current_context.mounts.push(() => {
+	const cleanup = callback();
+	if (typeof cleanup !== "function") return;
+
+	const outer_rendering = __currently_rendering;
+	__currently_rendering = rendering;
+	unmount(cleanup);
+	__currently_rendering = outer_rendering;
+    });
+}
+
+// Run around every batch of reactive updates that changes something in the component.
+const beforeUpdate = (callback) => {
+    assert(__current_context !== null, "beforeUpdate can only be called while a component is created");
+    __current_context.before_update.push(callback);
+}
+
+const afterUpdate = (callback) => {
+    assert(__current_context !== null, "afterUpdate can only be called while a component is created");
+    __current_context.after_update.push(callback);
+}
+
+// An error boundary replaces its children with the fallback once creating or updating them throws.
+const __error_boundary = (start, end, key, fallback) => ({
+    failed: false,
+    fail(error, context) {
+	__report_error(error, context);
+
+	if (this.failed) return;
+	this.failed = true;
+
+	__clear_between(start, end);
+	__run_unmounts(key);
+
+	const content = typeof fallback === "function" ? fallback(error) : fallback;
+	if (content === undefined || content === null) return;
+
+	__insert_before(content.nodeType === undefined ? document.createTextNode(String(content)) : content, end);
+    },
+});
+
+// The children of a boundary are created in a context of their own that shares everything with the
+// instance they're in, so blocks and instances inside can find the boundary through it.
+const __boundary_context = (context, boundary) => Object.assign(Object.create(context), { boundary });
+
+const __find_boundary = (context) => {
+    for (; context !== null; context = context.parent) {
+	if (context.boundary !== undefined) return context.boundary;
+    }
+
+    return null;
+}
+
+const __report_error = (error, context) => {
+    const name = context === null ? "Root" : context.name;
+    console.error(`[Lilac runtime error]: Error in component ${name}:`, error);
+}
+
+const setContext = (key, value) => {
+    assert(__current_context !== null, "setContext can only be called while a component is created");
+    __current_context.values.set(key, value);
+}
+
+const getContext = (key) => {
+    for (let context = __current_context; context !== null; context = context.parent) {
+	if (context.values.has(key)) return context.values.get(key);
+    }
+
+    return undefined;
+}
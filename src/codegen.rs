@@ -1,12 +1,23 @@
-use crate::{css::StyleSheet, job::Target, Node};
+use std::collections::HashMap;
+
+use crate::{css::StyleSheet, job::Target, source_map::SourceFile, Node};
 
 pub type CodegenResult = Result<String, String>;
 
 impl Node {
-    pub fn codegen(&self, target: Target, stylesheet: StyleSheet) -> CodegenResult {
+    /// `modules` is the bundle of the project's JS modules and `sources` the component files by
+    /// component name, which only the web target uses.
+    pub fn codegen(
+        &self,
+        target: Target,
+        stylesheet: StyleSheet,
+        modules: &str,
+        sources: &HashMap<String, SourceFile>,
+        release: bool,
+    ) -> CodegenResult {
         Ok(match target {
             Target::Unknown => return Err("Unknown target".to_string()),
-            Target::Web => self.full_js_codegen(stylesheet)?,
+            Target::Web => self.full_js_codegen(stylesheet, modules, sources, release)?,
             Target::GL => self.full_gl_codegen(stylesheet)?,
         })
     }
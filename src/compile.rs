@@ -16,7 +16,7 @@ pub fn _fill_holes(
     components_map: &HashMap<String, Component>,
     component_instance_children: &Option<Vec<Node>>,
 ) -> Result<(), String> {
-    if let Some(children) = children_of(root) {
+    for children in children_of(root) {
         for node in children {
             let mut recursion_stack = recursion_stack.clone();
 
@@ -39,7 +39,11 @@ pub fn _fill_holes(
                         *node = Node::Element(Element {
                             name: "".to_string(),
                             id: None,
+                            id_classes: vec![],
                             classes: None,
+                            class_directives: vec![],
+                            style_directives: vec![],
+                            transitions: vec![],
                             attributes: vec![],
                             children: children.clone(),
                         });
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use ress::tokens::{StringLit, Token};
+
+use crate::{
+    js_component_scoping::top_level_declarations, Attribute, AwaitBranch, ClassList, Component,
+    Dialect, Id, Node, ReactiveAttribute, SpreadAttribute,
+};
+
+/// What a component does with events, found from the `dispatch("name", detail)` calls in its
+/// code.
+#[derive(Debug, Default)]
+pub struct ComponentEvents {
+    /// Whether the component uses `dispatch`, so its instances need one.
+    pub dispatches: bool,
+    /// The names of the events dispatched.
+    pub names: Vec<String>,
+    /// Set when an event name isn't a string literal, so any event might be dispatched.
+    pub dynamic: bool,
+    /// Props read through `props.name`, handlers can be called that way too.
+    pub read_props: Vec<String>,
+}
+
+pub fn component_events(component: &Component) -> ComponentEvents {
+    let mut code = vec![];
+    let mut instances = vec![];
+
+    for child in &component.children {
+        walk(child, &mut code, &mut instances);
+    }
+
+    let mut events = ComponentEvents::default();
+
+    // A `dispatch` of the component's own isn't ours to replace.
+    let declares_dispatch = component.children.iter().any(|child| match child {
+        Node::ScriptTag(script) => {
+            top_level_declarations(&script.code).contains(&"dispatch".into())
+        }
+        _ => false,
+    });
+
+    for code in code {
+        scan(code, &mut events);
+    }
+
+    if declares_dispatch {
+        events.dispatches = false;
+        events.names.clear();
+        events.dynamic = false;
+    }
+
+    events
+}
+
+/// Warns about `on<event>` handlers given to component instances for events the component never
+/// dispatches.
+pub fn lint_component_events(components: &HashMap<String, Component>) -> Vec<String> {
+    let mut names = components.keys().collect::<Vec<_>>();
+    names.sort();
+
+    let events = components
+        .iter()
+        .map(|(name, component)| (name, component_events(component)))
+        .collect::<HashMap<_, _>>();
+
+    let mut warnings = vec![];
+
+    for name in names {
+        // Events are only a thing on the web.
+        if components[name].dialect == Dialect::RsLilac {
+            continue;
+        }
+
+        let mut code = vec![];
+        let mut instances = vec![];
+
+        for child in &components[name].children {
+            walk(child, &mut code, &mut instances);
+        }
+
+        for (instance, props) in instances {
+            let Some(events) = events.get(&instance.to_string()) else {
+                continue;
+            };
+
+            if events.dynamic {
+                continue;
+            }
+
+            for prop in props {
+                let Some(event) = prop.name().strip_prefix("on") else {
+                    continue;
+                };
+
+                if event.is_empty()
+                    || events.names.iter().any(|n| n == event)
+                    || events.read_props.iter().any(|p| p == prop.name())
+                {
+                    continue;
+                }
+
+                warnings.push(format!(
+                    "\"{}\" Handler \"{}\" on <{}> never runs, {} never dispatches \"{}\".",
+                    name,
+                    prop.name(),
+                    instance,
+                    instance,
+                    event
+                ));
+            }
+        }
+    }
+
+    warnings
+}
+
+fn scan(code: &str, events: &mut ComponentEvents) {
+    let tokens = ress::Scanner::new(code)
+        .flatten()
+        .filter(|item| !item.token.is_comment())
+        .map(|item| item.token)
+        .collect::<Vec<_>>();
+
+    for (i, token) in tokens.iter().enumerate() {
+        let after_period = i > 0 && tokens[i - 1].matches_punct_str(".");
+
+        if token.matches_ident_str("props") && !after_period {
+            if let (Some(period), Some(Token::Ident(name))) = (tokens.get(i + 1), tokens.get(i + 2))
+            {
+                if period.matches_punct_str(".") {
+                    events.read_props.push(name.to_string());
+                }
+            }
+        }
+
+        if !token.matches_ident_str("dispatch") || after_period {
+            continue;
+        }
+
+        events.dispatches = true;
+
+        let called = tokens.get(i + 1).is_some_and(|t| t.matches_punct_str("("));
+
+        match tokens.get(i + 2) {
+            Some(Token::String(StringLit::Single(name) | StringLit::Double(name))) if called => {
+                events.names.push(name.content.to_string());
+            }
+            _ => events.dynamic = true,
+        }
+    }
+}
+
+/// Collects the JS code in the template and the props of the component instances in it.
+fn walk<'a>(
+    node: &'a Node,
+    code: &mut Vec<&'a str>,
+    instances: &mut Vec<(&'a str, &'a [Attribute])>,
+) {
+    let attribute_code = |attribute: &'a Attribute| match attribute {
+        Attribute::Reactive(ReactiveAttribute { value, .. })
+        | Attribute::Spread(SpreadAttribute { value }) => Some(value.as_str()),
+        Attribute::Static(_) => None,
+    };
+
+    let children: Vec<&Node> = match node {
+        Node::ScriptTag(script) => {
+            code.push(&script.code);
+            return;
+        }
+        Node::ReactiveText(text, _) => {
+            code.push(text);
+            return;
+        }
+        Node::Const { value, .. } => {
+            code.push(value);
+            return;
+        }
+        Node::Element(e) => {
+            code.extend(e.attributes.iter().filter_map(attribute_code));
+
+            if let Some(Id::Reactive(id)) = &e.id {
+                code.push(id);
+            }
+
+            if let Some(ClassList::Reactive(classes)) = &e.classes {
+                code.push(classes);
+            }
+
+            for directive in e.class_directives.iter().chain(&e.style_directives) {
+                code.push(&directive.value);
+            }
+
+            e.children.iter().collect()
+        }
+        Node::ComponentHole {
+            name,
+            props,
+            children,
+            ..
+        } => {
+            code.extend(props.iter().filter_map(attribute_code));
+            instances.push((name, props));
+
+            children.iter().flatten().collect()
+        }
+        // Instances already filled in have events of their own.
+        Node::Component(_) => return,
+        Node::ConditionalElements {
+            condition,
+            children,
+        } => {
+            code.push(condition);
+            children.iter().collect()
+        }
+        Node::Loop {
+            iteratable,
+            children,
+            empty,
+            ..
+        } => {
+            code.push(iteratable);
+            children.iter().chain(empty).collect()
+        }
+        Node::Key { key, children } => {
+            code.push(key);
+            children.iter().collect()
+        }
+        Node::ErrorBoundary {
+            fallback, children, ..
+        } => {
+            code.extend(fallback.as_deref());
+            children.iter().collect()
+        }
+        Node::Await {
+            promise,
+            pending,
+            then,
+            catch,
+        } => {
+            code.push(promise);
+
+            let branches = then.iter().chain(catch.iter());
+            pending
+                .iter()
+                .chain(branches.flat_map(|b: &AwaitBranch| &b.children))
+                .collect()
+        }
+        Node::Text(_) | Node::StyleTag(_) => return,
+    };
+
+    for child in children {
+        walk(child, code, instances);
+    }
+}
@@ -4,6 +4,8 @@ pub type StyleSheet = Vec<Rule>;
 pub struct Rule {
     pub selector: Selector,
     pub properties: Vec<Property>,
+    /// The preludes of the at-rules the rule is in, outermost first, like `@media (width < 600px)`.
+    pub at_rules: Vec<String>,
 }
 
 impl Rule {
@@ -15,20 +17,80 @@ impl Rule {
 #[derive(Debug, Clone)]
 pub struct Property {
     pub name: String,
-    pub value: Vec<String>,
+    pub value: Value,
+}
+
+impl Property {
+    pub fn is_custom(&self) -> bool {
+        self.name.starts_with("--")
+    }
+}
+
+pub type Value = Vec<ValueComponent>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueComponent {
+    /// Identifiers, numbers, dimensions, hashes and operators.
+    Token(String),
+    /// A quoted string, including its quotes.
+    String(String),
+    Comma,
+    /// A function call such as `var(--accent, red)`. Plain parentheses, as used in `calc()`, are
+    /// functions with an empty name.
+    Function {
+        name: String,
+        arguments: Vec<ValueComponent>,
+    },
+    /// Text kept exactly as written. Custom property values don't have to be valid CSS until
+    /// they are substituted with `var()` so they are never parsed.
+    Raw(String),
+}
+
+impl ValueComponent {
+    pub fn to_css(&self) -> String {
+        match self {
+            ValueComponent::Token(t) | ValueComponent::String(t) | ValueComponent::Raw(t) => {
+                t.clone()
+            }
+            ValueComponent::Comma => ",".to_string(),
+            ValueComponent::Function { name, arguments } => {
+                format!("{}({})", name, value_to_css(arguments))
+            }
+        }
+    }
+}
+
+pub fn value_to_css(value: &[ValueComponent]) -> String {
+    let mut css = String::new();
+
+    for (i, component) in value.iter().enumerate() {
+        if i > 0 && *component != ValueComponent::Comma {
+            css.push(' ');
+        }
+        css.push_str(&component.to_css());
+    }
+
+    css
 }
 
 #[derive(Debug, Clone)]
 pub enum Selector {
+    /// The declarations of an at-rule like `@font-face`, which aren't for any element.
     None,
     Tag(String),
     Class(String),
     ID(String),
     Pseduo(String),
+    Attribute(String),
+    /// Several simple selectors that must all match the same element, e.g. `a.link:hover`.
+    Compound(Vec<Selector>),
     Descendant(Box<Selector>, Box<Selector>),
     Child(Box<Selector>, Box<Selector>),
     NextSibling(Box<Selector>, Box<Selector>),
+    SubsequentSibling(Box<Selector>, Box<Selector>),
     All,
+    /// A `from`, `to` or percentage in `@keyframes`.
+    Keyframe(String),
 }
 
 impl Selector {
@@ -46,318 +108,724 @@ pub struct CSSParseError {
     pub message: String,
 }
 
-#[derive(PartialEq, Debug, Copy, Clone)]
-enum ParsingContext {
-    Selector,
-    PropertyName,
-    AwaitingColon,
-    PropertyValue,
+/// Parses a stylesheet, flattening nested rules into plain rules. Locations in errors are byte
+/// offsets into `input`.
+pub fn parse(input: &str) -> Result<StyleSheet, CSSParseError> {
+    let input = blank_comments(input);
+
+    let mut pos = 0;
+    let mut ss: StyleSheet = vec![];
+
+    parse_block(&input, &mut pos, &[], &[], &mut ss)?;
+
+    Ok(ss)
 }
 
-fn assert_state(
-    state: ParsingContext,
-    expected: ParsingContext,
-    token: &str,
-    pos: usize,
+/// Parses the inside of a rule up to and including its closing brace. Nested rules are added to
+/// `ss` after the rule itself. Without any selectors this is the top level, or an at-rule in it,
+/// which only holds rules.
+fn parse_block(
+    input: &str,
+    pos: &mut usize,
+    selectors: &[Selector],
+    at_rules: &[String],
+    ss: &mut StyleSheet,
 ) -> Result<(), CSSParseError> {
-    if state != expected {
-        return Err(CSSParseError {
-            location: pos,
-            message: format!("Unexpected {:?}", token),
-        });
-    }
-    Ok(())
-}
+    let top_level = selectors.is_empty() && at_rules.is_empty();
+    let index = ss.len();
 
-fn join_colons(s: &Vec<Token>, pos: usize) -> Result<Vec<Token>, CSSParseError> {
-    let mut tokens: Vec<Token> = vec![];
+    let mut properties = vec![];
+    let mut has_nested_rules = false;
 
-    let mut colons = 0;
+    loop {
+        skip_whitespace(input, pos);
 
-    for t in s {
-        if t == &Token::Colon {
-            colons += 1;
-        } else {
-            if colons == 0 {
-                tokens.push(t.clone());
-            } else {
-                match t {
-                    Token::Identifier(s) => {
-                        if colons == 1 {
-                            tokens.push(Token::Identifier(format!(":{}", s)));
-                        } else if colons == 2 {
-                            tokens.push(Token::Identifier(format!("::{}", s)));
-                        } else {
-                            return Err(CSSParseError {
-                                location: pos,
-                                message: "Too many colons".to_string(),
-                            });
-                        }
+        match peek(input, *pos) {
+            None if top_level => break,
+            None => {
+                return Err(CSSParseError {
+                    location: *pos,
+                    message: "Unbalanced curly braces".to_string(),
+                })
+            }
+            Some('}') if top_level => {
+                return Err(CSSParseError {
+                    location: *pos,
+                    message: "Unbalanced curly braces".to_string(),
+                })
+            }
+            Some('}') => {
+                *pos += 1;
+                break;
+            }
+            Some(';') if !selectors.is_empty() => {
+                *pos += 1;
+                continue;
+            }
+            Some('@') => {
+                parse_at_rule(input, pos, selectors, at_rules, ss)?;
+                has_nested_rules = true;
+                continue;
+            }
+            _ => {}
+        }
+
+        let start = *pos;
+        let is_custom = input[start..].starts_with("--");
+
+        match find_delimiter(input, start, is_custom) {
+            Some((at, '{')) => {
+                *pos = at + 1;
+
+                let prelude = &input[start..at];
+
+                let nested = match selectors {
+                    [] => parse_selector_list(prelude, None, start)?,
+                    [Selector::None | Selector::Keyframe(_), ..] => {
+                        return Err(CSSParseError {
+                            location: start,
+                            message: "Rules can't be nested in this at-rule".to_string(),
+                        })
                     }
                     _ => {
-                        return Err(CSSParseError {
-                            location: pos,
-                            message: "Unexpected token after colon".to_string(),
-                        });
+                        let mut nested = vec![];
+                        for parent in selectors {
+                            nested.extend(parse_selector_list(prelude, Some(parent), start)?);
+                        }
+                        nested
                     }
-                }
-                colons = 0;
+                };
+
+                parse_block(input, pos, &nested, at_rules, ss)?;
+                has_nested_rules = true;
+            }
+            Some((at, c)) if selectors.is_empty() => {
+                return Err(CSSParseError {
+                    location: at,
+                    message: match c {
+                        '}' => "Expected {".to_string(),
+                        _ => "Unexpected \";\"".to_string(),
+                    },
+                })
+            }
+            Some((at, _)) => {
+                properties.push(parse_declaration(&input[start..at], start)?);
+                *pos = at;
+            }
+            None if selectors.is_empty() => {
+                return Err(CSSParseError {
+                    location: input.len(),
+                    message: "Expected {".to_string(),
+                })
+            }
+            None => {
+                return Err(CSSParseError {
+                    location: input.len(),
+                    message: "Unbalanced curly braces".to_string(),
+                })
             }
         }
     }
 
-    Ok(tokens)
+    // Rules that only exist to hold nested rules don't need to be emitted.
+    if !properties.is_empty() || !has_nested_rules {
+        for (i, selector) in selectors.iter().enumerate() {
+            ss.insert(
+                index + i,
+                Rule {
+                    selector: selector.clone(),
+                    properties: properties.clone(),
+                    at_rules: at_rules.to_vec(),
+                },
+            );
+        }
+    }
+
+    Ok(())
 }
 
-fn parse_selector(s: &Vec<Token>, pos: usize, colons: usize) -> Result<Selector, CSSParseError> {
-    assert!(!s.is_empty());
+/// Parses an at-rule with a block. Conditional ones like `@media` hold whatever the block they're
+/// in does, so rules at the top level or declarations in a rule.
+fn parse_at_rule(
+    input: &str,
+    pos: &mut usize,
+    selectors: &[Selector],
+    at_rules: &[String],
+    ss: &mut StyleSheet,
+) -> Result<(), CSSParseError> {
+    let start = *pos;
 
-    let s = join_colons(s, pos)?;
+    let name = input[start + 1..]
+        .split(|c: char| c.is_whitespace() || c == '(' || c == '{' || c == ';')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    let at = match find_delimiter(input, start, false) {
+        Some((at, '{')) => at,
+        _ => {
+            return Err(CSSParseError {
+                location: start,
+                message: format!("@{} is not supported, only at-rules with a block are", name),
+            })
+        }
+    };
 
-    if s.len() == 1 {
-        if let Token::Identifier(s) = &s[0] {
-            if s == "*" {
-                return Ok(Selector::All);
-            }
+    *pos = at + 1;
 
-            if s.starts_with("#") {
-                if s.len() < 2 {
-                    return Err(CSSParseError {
-                        location: pos,
-                        message: "Empty ID name.".to_string(),
-                    });
-                }
-                return Ok(Selector::ID(s[1..].to_string()));
-            }
-            if s.starts_with(".") {
-                if s.len() < 2 {
-                    return Err(CSSParseError {
-                        location: pos,
-                        message: "Empty Class name.".to_string(),
-                    });
-                }
-                return Ok(Selector::Class(s[1..].to_string()));
-            }
-            if s.starts_with(":") {
-                if s.len() < 2 {
-                    return Err(CSSParseError {
-                        location: pos,
-                        message: "Empty Pseudo name.".to_string(),
-                    });
-                }
-                return Ok(Selector::Pseduo(s[1..].to_string()));
-            }
+    let prelude = input[start..at]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
 
-            assert!(s.len() > 0);
+    let mut at_rules = at_rules.to_vec();
+    at_rules.push(prelude);
 
-            return Ok(Selector::Tag(s.clone()));
+    match name.as_str() {
+        "media" | "supports" | "container" | "layer" => {
+            parse_block(input, pos, selectors, &at_rules, ss)
+        }
+        "font-face" | "page" | "property" | "counter-style" => {
+            parse_block(input, pos, &[Selector::None], &at_rules, ss)
         }
+        name if name.ends_with("keyframes") => parse_keyframes(input, pos, &at_rules, ss),
+        _ => Err(CSSParseError {
+            location: start,
+            message: format!("Unknown at-rule @{}", name),
+        }),
     }
+}
 
-    let a = parse_selector(&s[0..1].to_vec(), pos, colons)?;
+fn parse_keyframes(
+    input: &str,
+    pos: &mut usize,
+    at_rules: &[String],
+    ss: &mut StyleSheet,
+) -> Result<(), CSSParseError> {
+    loop {
+        skip_whitespace(input, pos);
 
-    match &s[1] {
-        Token::Identifier(oper) => {
-            if oper == ">" {
-                let b = parse_selector(&s[2..].to_vec(), pos, colons)?;
-                return Ok(Selector::Child(Box::new(a), Box::new(b)));
-            }
-            if oper == "+" {
-                let b = parse_selector(&s[2..].to_vec(), pos, colons)?;
-                return Ok(Selector::NextSibling(Box::new(a), Box::new(b)));
-            }
+        if peek(input, *pos) == Some('}') {
+            *pos += 1;
+            return Ok(());
         }
-        _ => {}
-    }
 
-    let b = parse_selector(&s[1..].to_vec(), pos, colons)?;
-    return Ok(Selector::Descendant(Box::new(a), Box::new(b)));
+        let start = *pos;
+
+        let Some((at, '{')) = find_delimiter(input, start, false) else {
+            return Err(CSSParseError {
+                location: start,
+                message: "Expected keyframe".to_string(),
+            });
+        };
+
+        *pos = at + 1;
+
+        let keyframe = input[start..at]
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        parse_block(input, pos, &[Selector::Keyframe(keyframe)], at_rules, ss)?;
+    }
 }
 
-pub fn parse(input: &str) -> Result<StyleSheet, CSSParseError> {
+fn parse_declaration(text: &str, location: usize) -> Result<Property, CSSParseError> {
+    let colon = text.find(':').ok_or(CSSParseError {
+        location,
+        message: "Expected colon".to_string(),
+    })?;
+
+    let name = text[..colon].trim().to_string();
+
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(CSSParseError {
+            location,
+            message: format!("Invalid property name {:?}", name),
+        });
+    }
+
+    let value_text = text[colon + 1..].trim();
+    let value_location = location + colon + 1;
+
+    if name.starts_with("--") {
+        return Ok(Property {
+            name,
+            value: vec![ValueComponent::Raw(value_text.to_string())],
+        });
+    }
+
+    if value_text.is_empty() {
+        return Err(CSSParseError {
+            location: value_location,
+            message: format!("Expected value for property {}", name),
+        });
+    }
+
     let mut pos = 0;
+    let value = parse_value(value_text, &mut pos, value_location, false)?;
 
-    let mut ss: StyleSheet = vec![];
-    let mut state = ParsingContext::Selector;
+    Ok(Property { name, value })
+}
 
-    let mut current_style = Rule {
-        selector: Selector::None,
-        properties: vec![],
+fn parse_value(
+    text: &str,
+    pos: &mut usize,
+    location: usize,
+    in_function: bool,
+) -> Result<Value, CSSParseError> {
+    let mut components = vec![];
+    let mut token = String::new();
+
+    let flush = |token: &mut String, components: &mut Vec<ValueComponent>| {
+        if !token.is_empty() {
+            components.push(ValueComponent::Token(token.clone()));
+            token.clear();
+        }
     };
 
-    let mut current_property = Property {
-        name: String::new(),
-        value: vec![],
-    };
+    while let Some(c) = peek(text, *pos) {
+        if c.is_whitespace() {
+            flush(&mut token, &mut components);
+            *pos += c.len_utf8();
+        } else if c == ',' {
+            flush(&mut token, &mut components);
+            components.push(ValueComponent::Comma);
+            *pos += 1;
+        } else if c == '"' || c == '\'' {
+            flush(&mut token, &mut components);
+            components.push(ValueComponent::String(take_string(text, pos, location)?));
+        } else if c == '(' {
+            let name = token.clone();
+            token.clear();
+            *pos += 1;
 
-    let mut selector_tokens = vec![];
+            let mut after = *pos;
+            skip_whitespace(text, &mut after);
+            let quoted = matches!(peek(text, after), Some('"') | Some('\''));
+
+            let arguments = if name.eq_ignore_ascii_case("url") && !quoted {
+                // Unquoted urls can contain commas and slashes that must not be touched.
+                let end = text[*pos..].find(')').ok_or(CSSParseError {
+                    location: location + *pos,
+                    message: "Expected )".to_string(),
+                })? + *pos;
+                let url = text[*pos..end].trim().to_string();
+                *pos = end + 1;
+                vec![ValueComponent::Token(url)]
+            } else {
+                parse_value(text, pos, location, true)?
+            };
 
-    while let Some(token) = next_token(input, &mut pos)? {
-        if state == ParsingContext::AwaitingColon {
-            if token != Token::Colon {
+            components.push(ValueComponent::Function { name, arguments });
+        } else if c == ')' {
+            if !in_function {
                 return Err(CSSParseError {
-                    location: pos,
-                    message: "Expected colon".to_string(),
+                    location: location + *pos,
+                    message: "Unexpected )".to_string(),
                 });
             }
-            state = ParsingContext::PropertyValue;
-            continue;
+            flush(&mut token, &mut components);
+            *pos += 1;
+            return Ok(components);
+        } else {
+            token.push(c);
+            *pos += c.len_utf8();
         }
+    }
 
-        match token {
-            Token::OpenBrace => {
-                assert_state(state, ParsingContext::Selector, "{", pos)?;
-                state = ParsingContext::PropertyName;
+    if in_function {
+        return Err(CSSParseError {
+            location: location + *pos,
+            message: "Expected )".to_string(),
+        });
+    }
 
-                current_style.selector = parse_selector(&selector_tokens, pos, 0)?;
-                selector_tokens.clear();
-            }
-            Token::CloseBrace => {
-                assert_state(state, ParsingContext::PropertyName, "}.", pos)?;
-                ss.push(current_style.clone());
-                current_style.properties.clear();
-                state = ParsingContext::Selector;
-            }
-            Token::Colon => {
-                assert_state(state, ParsingContext::Selector, ":", pos)?;
-                selector_tokens.push(token);
-            }
-            Token::Semicolon => {
-                assert_state(state, ParsingContext::PropertyValue, ";", pos)?;
-                current_style.properties.push(current_property.clone());
-                current_property.name.clear();
-                current_property.value.clear();
-                state = ParsingContext::PropertyName;
+    flush(&mut token, &mut components);
+
+    Ok(components)
+}
+
+/// Parses a comma separated selector list. When `parent` is set the selectors are nested rules
+/// and `&` refers to the parent selector.
+fn parse_selector_list(
+    text: &str,
+    parent: Option<&Selector>,
+    location: usize,
+) -> Result<Vec<Selector>, CSSParseError> {
+    let mut selectors = vec![];
+    let mut start = 0;
+
+    loop {
+        let end = find_top_level_comma(text, start).unwrap_or(text.len());
+        selectors.push(parse_selector(&text[start..end], parent, location + start)?);
+
+        if end == text.len() {
+            break;
+        }
+        start = end + 1;
+    }
+
+    Ok(selectors)
+}
+
+fn parse_selector(
+    text: &str,
+    parent: Option<&Selector>,
+    location: usize,
+) -> Result<Selector, CSSParseError> {
+    let mut pos = 0;
+    let mut selector: Option<Selector> = None;
+    let mut combinator: Option<char> = None;
+    let mut uses_parent = false;
+
+    loop {
+        skip_whitespace(text, &mut pos);
+
+        let c = match peek(text, pos) {
+            Some(c) => c,
+            None => break,
+        };
+
+        if c == '>' || c == '+' || c == '~' {
+            if combinator.is_some() {
+                return Err(CSSParseError {
+                    location: location + pos,
+                    message: format!("Unexpected {:?}", c),
+                });
             }
-            Token::Identifier(s) => match state {
-                ParsingContext::Selector => {
-                    selector_tokens.push(Token::Identifier(s));
-                }
-                ParsingContext::PropertyName => {
-                    current_property.name = s;
-                    state = ParsingContext::AwaitingColon;
-                }
-                ParsingContext::PropertyValue => {
-                    current_property.value.push(s);
+            combinator = Some(c);
+            pos += 1;
+            continue;
+        }
+
+        let compound = parse_compound_selector(text, &mut pos, parent, &mut uses_parent, location)?;
+
+        let left = match selector.take() {
+            Some(left) => left,
+            // Relative selectors such as `> .item` are relative to the parent.
+            None if combinator.is_some() => match parent {
+                Some(parent) => {
+                    uses_parent = true;
+                    parent.clone()
                 }
-                ParsingContext::AwaitingColon => {
-                    unreachable!();
+                None => {
+                    return Err(CSSParseError {
+                        location,
+                        message: "Expected selector before combinator".to_string(),
+                    })
                 }
             },
-            Token::String(s) => {
-                assert_state(state, ParsingContext::PropertyValue, "string", pos)?;
-                current_property.value.push(s);
+            None => {
+                selector = Some(compound);
+                continue;
             }
-        }
+        };
+
+        selector = Some(combine(left, combinator.take().unwrap_or(' '), compound));
     }
 
-    if state != ParsingContext::Selector {
+    if combinator.is_some() {
         return Err(CSSParseError {
-            location: pos,
-            message: "Unbalanced curly braces".to_string(),
+            location: location + pos,
+            message: "Expected selector after combinator".to_string(),
         });
     }
 
-    Ok(ss)
+    let selector = selector.ok_or(CSSParseError {
+        location,
+        message: "Empty selector".to_string(),
+    })?;
+
+    Ok(match parent {
+        Some(parent) if !uses_parent => combine(parent.clone(), ' ', selector),
+        _ => selector,
+    })
 }
 
-#[derive(PartialEq, Debug, Clone)]
-enum Token {
-    Identifier(String),
-    String(String),
-    Colon,
-    Semicolon,
-    OpenBrace,
-    CloseBrace,
+fn combine(a: Selector, combinator: char, b: Selector) -> Selector {
+    let (a, b) = (Box::new(a), Box::new(b));
+    match combinator {
+        '>' => Selector::Child(a, b),
+        '+' => Selector::NextSibling(a, b),
+        '~' => Selector::SubsequentSibling(a, b),
+        _ => Selector::Descendant(a, b),
+    }
 }
 
-fn skip_whitespace_and_commnets(input: &str, pos: &mut usize) {
-    let mut in_comment = false;
+fn parse_compound_selector(
+    text: &str,
+    pos: &mut usize,
+    parent: Option<&Selector>,
+    uses_parent: &mut bool,
+    location: usize,
+) -> Result<Selector, CSSParseError> {
+    let mut parts = vec![];
+
+    while let Some(c) = peek(text, *pos) {
+        if c.is_whitespace() || c == '>' || c == '+' || c == '~' {
+            break;
+        }
 
-    if input[*pos..].starts_with("/*") {
-        in_comment = true;
-        *pos += 2;
-    }
+        let start = *pos;
 
-    while *pos < input.len() && (in_comment || input.chars().nth(*pos).unwrap().is_whitespace()) {
-        if in_comment {
-            if input[*pos..].starts_with("*/") {
-                in_comment = false;
-                *pos += 2;
-                continue;
+        let part = match c {
+            '&' => {
+                *pos += 1;
+                *uses_parent = true;
+                parent.cloned().ok_or(CSSParseError {
+                    location: location + start,
+                    message: "& can only be used in nested rules".to_string(),
+                })?
             }
-        } else {
-            if input[*pos..].starts_with("/*") {
-                in_comment = true;
-                *pos += 2;
-                continue;
+            '*' => {
+                *pos += 1;
+                Selector::All
             }
-        }
+            '.' => {
+                *pos += 1;
+                Selector::Class(take_ident(text, pos, location, "Empty Class name.")?)
+            }
+            '#' => {
+                *pos += 1;
+                Selector::ID(take_ident(text, pos, location, "Empty ID name.")?)
+            }
+            ':' => {
+                *pos += 1;
+                let mut pseudo = String::new();
+                if peek(text, *pos) == Some(':') {
+                    *pos += 1;
+                    pseudo.push(':');
+                }
+                pseudo.push_str(&take_ident(text, pos, location, "Empty Pseudo name.")?);
+                if peek(text, *pos) == Some('(') {
+                    let end = find_closing_bracket(text, *pos, '(', ')', location)?;
+                    pseudo.push_str(&text[*pos..=end]);
+                    *pos = end + 1;
+                }
+                Selector::Pseduo(pseudo)
+            }
+            '[' => {
+                let end = find_closing_bracket(text, *pos, '[', ']', location)?;
+                let attribute = text[*pos + 1..end].to_string();
+                *pos = end + 1;
+                Selector::Attribute(attribute)
+            }
+            c if is_ident_char(c) => {
+                if !parts.is_empty() {
+                    return Err(CSSParseError {
+                        location: location + start,
+                        message: "Type selectors must come first in a compound selector"
+                            .to_string(),
+                    });
+                }
+                Selector::Tag(take_ident(text, pos, location, "Expected tag name.")?)
+            }
+            c => {
+                return Err(CSSParseError {
+                    location: location + start,
+                    message: format!("Unexpected {:?} in selector", c),
+                })
+            }
+        };
 
-        *pos += 1;
+        parts.push(part);
     }
+
+    Ok(if parts.len() == 1 {
+        parts.remove(0)
+    } else {
+        Selector::Compound(parts)
+    })
 }
 
-fn next_token(input: &str, pos: &mut usize) -> Result<Option<Token>, CSSParseError> {
-    skip_whitespace_and_commnets(input, pos);
-    if *pos >= input.len() {
-        return Ok(None);
-    }
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_' || c == '\\' || !c.is_ascii()
+}
 
+fn take_ident(
+    text: &str,
+    pos: &mut usize,
+    location: usize,
+    empty_message: &str,
+) -> Result<String, CSSParseError> {
     let start = *pos;
-    let c = input.chars().nth(*pos).unwrap();
-    if c == '{' {
-        *pos += 1;
-        return Ok(Some(Token::OpenBrace));
+
+    while let Some(c) = peek(text, *pos) {
+        if !is_ident_char(c) {
+            break;
+        }
+        *pos += c.len_utf8();
+
+        if c == '\\' {
+            if let Some(escaped) = peek(text, *pos) {
+                *pos += escaped.len_utf8();
+            }
+        }
     }
-    if c == '}' {
-        *pos += 1;
-        return Ok(Some(Token::CloseBrace));
+
+    if *pos == start {
+        return Err(CSSParseError {
+            location: location + start,
+            message: empty_message.to_string(),
+        });
     }
-    if c == ':' {
-        *pos += 1;
-        return Ok(Some(Token::Colon));
+
+    Ok(text[start..*pos].to_string())
+}
+
+fn take_string(text: &str, pos: &mut usize, location: usize) -> Result<String, CSSParseError> {
+    let start = *pos;
+    let quote = peek(text, start).unwrap();
+    *pos += 1;
+
+    let mut escaped = false;
+
+    while let Some(c) = peek(text, *pos) {
+        *pos += c.len_utf8();
+
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return Ok(text[start..*pos].to_string());
+        }
     }
-    if c == ';' {
-        *pos += 1;
-        return Ok(Some(Token::Semicolon));
+
+    Err(CSSParseError {
+        location: location + *pos,
+        message: "Unterminated string".to_string(),
+    })
+}
+
+fn find_closing_bracket(
+    text: &str,
+    start: usize,
+    open: char,
+    close: char,
+    location: usize,
+) -> Result<usize, CSSParseError> {
+    let mut depth = 0;
+
+    for (i, c) in text[start..].char_indices() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Ok(start + i);
+            }
+        }
     }
-    if c == '"' {
-        *pos += 1;
-        let mut escaped = false;
-        while *pos < input.len() {
-            let c = input.chars().nth(*pos).unwrap();
-            if !escaped && c == '\\' {
-                escaped = true;
-            } else if escaped {
+
+    Err(CSSParseError {
+        location: location + start,
+        message: format!("Expected {}", close),
+    })
+}
+
+fn find_top_level_comma(text: &str, start: usize) -> Option<usize> {
+    let mut depth = 0;
+
+    for (i, c) in text[start..].char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ',' if depth == 0 => return Some(start + i),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Finds the first `{`, `;` or `}` that isn't inside brackets or a string. Custom property
+/// values may contain blocks so with `allow_blocks` set braces are only matched in pairs.
+fn find_delimiter(input: &str, start: usize, allow_blocks: bool) -> Option<(usize, char)> {
+    let mut depth = 0;
+    let mut block_depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (i, c) in input[start..].char_indices() {
+        if let Some(q) = quote {
+            if escaped {
                 escaped = false;
-            } else if c == '"' {
-                *pos += 1;
-                return Ok(Some(Token::String(input[start..*pos].to_string())));
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
             }
-            *pos += 1;
+            continue;
         }
-        return Err(CSSParseError {
-            location: *pos,
-            message: "Unterminated string".to_string(),
-        });
-    }
 
-    while *pos < input.len() {
-        let c = input.chars().nth(*pos).unwrap();
-        if !id_char(c) {
-            break;
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            '{' if allow_blocks => block_depth += 1,
+            '}' if allow_blocks && block_depth > 0 => block_depth -= 1,
+            '{' | ';' | '}' if depth <= 0 && block_depth == 0 => return Some((start + i, c)),
+            _ => {}
         }
-        *pos += 1;
     }
 
-    if *pos > start {
-        return Ok(Some(Token::Identifier(input[start..*pos].to_string())));
+    None
+}
+
+/// Replaces comments with whitespace so that byte offsets stay the same.
+fn blank_comments(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    let mut quote = None;
+    let mut escaped = false;
+    let mut in_comment = false;
+
+    while let Some(c) = chars.next() {
+        if in_comment {
+            if c == '*' && chars.peek() == Some(&'/') {
+                chars.next();
+                output.push_str("  ");
+                in_comment = false;
+            } else {
+                output.push_str(&" ".repeat(c.len_utf8()));
+            }
+            continue;
+        }
+
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+        } else if c == '"' || c == '\'' {
+            quote = Some(c);
+        } else if c == '/' && chars.peek() == Some(&'*') {
+            chars.next();
+            output.push_str("  ");
+            in_comment = true;
+            continue;
+        }
+
+        output.push(c);
     }
 
-    Ok(None)
+    output
+}
+
+fn skip_whitespace(input: &str, pos: &mut usize) {
+    while let Some(c) = peek(input, *pos) {
+        if !c.is_whitespace() {
+            break;
+        }
+        *pos += c.len_utf8();
+    }
 }
 
-fn id_char(c: char) -> bool {
-    !c.is_whitespace() && c != '"' && c != ':' && c != ';' && c != '{' && c != '}'
+fn peek(input: &str, pos: usize) -> Option<char> {
+    input.get(pos..).and_then(|s| s.chars().next())
 }
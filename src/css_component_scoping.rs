@@ -1,4 +1,4 @@
-use crate::{css::*, utils::children_of, ClassList, Component, Element, Id, Node};
+use crate::{css::*, utils::children_of, ClassList, Component, Directive, Element, Id, Node};
 
 pub fn scope_css_to_component(
     mut component: Component,
@@ -56,10 +56,12 @@ fn replace_refs(
                         add_class(e, class);
                     }
                 }
-                Id::Reactive(expr) => {
-                    todo!();
-                    // let class = format!("id{}{}", prefix, );
-                    // e.id = Some(Id::Reactive(format!("\"{}\" + ({})", prefix , expr.clone())));
+                // Toggled by the id's update function, so the expression is only evaluated once.
+                Id::Reactive(_) => {
+                    e.id_classes = ids
+                        .iter()
+                        .map(|id| (id.clone(), format!("id{}{}", prefix, id)))
+                        .collect();
                 }
             }
         }
@@ -90,6 +92,19 @@ fn replace_refs(
             }
         }
 
+        let scoped_directives = e
+            .class_directives
+            .iter()
+            .filter(|d| classes.contains(&d.name))
+            .map(|d| Directive {
+                name: format!("class{}{}", prefix, d.name),
+                value: d.value.clone(),
+            })
+            .collect::<Vec<Directive>>();
+
+        e.class_directives.extend(scoped_directives);
+
+        // Added after the class list has been scoped so the id class doesn't get prefixed again.
         if tags.contains(&e.name) {
             add_class(e, format!("tag{}{}", prefix, e.name));
         }
@@ -99,7 +114,7 @@ fn replace_refs(
         }
     }
 
-    if let Some(children) = children_of(node) {
+    for children in children_of(node) {
         for node in children {
             replace_refs(node, prefix, ids, classes, tags, all);
         }
@@ -113,20 +128,62 @@ fn handle_css(css: &mut StyleSheet, prefix: &str) -> (Vec<String>, Vec<String>,
     let mut all = false;
 
     for rule in css {
-        if let Selector::Tag(t) = &mut rule.selector {
-            tags.push(t.clone());
-            rule.selector = Selector::Class(format!("tag{}{}", prefix, t));
-        } else if let Selector::Class(c) = &mut rule.selector {
-            classes.push(c.clone());
-            rule.selector = Selector::Class(format!("class{}{}", prefix, c));
-        } else if let Selector::ID(id) = &mut rule.selector {
-            ids.push(id.clone());
-            rule.selector = Selector::Class(format!("id{}{}", prefix, id));
-        } else if let Selector::All = rule.selector {
-            all = true;
-            rule.selector = Selector::Class(format!("all{}", prefix));
-        }
+        scope_selector(
+            &mut rule.selector,
+            prefix,
+            &mut ids,
+            &mut classes,
+            &mut tags,
+            &mut all,
+        );
     }
 
     (ids, classes, tags, all)
 }
+
+fn scope_selector(
+    selector: &mut Selector,
+    prefix: &str,
+    ids: &mut Vec<String>,
+    classes: &mut Vec<String>,
+    tags: &mut Vec<String>,
+    all: &mut bool,
+) {
+    match selector {
+        Selector::Tag(t) => {
+            if !tags.contains(t) {
+                tags.push(t.clone());
+            }
+            *selector = Selector::Class(format!("tag{}{}", prefix, t));
+        }
+        Selector::Class(c) => {
+            if !classes.contains(c) {
+                classes.push(c.clone());
+            }
+            *selector = Selector::Class(format!("class{}{}", prefix, c));
+        }
+        Selector::ID(id) => {
+            if !ids.contains(id) {
+                ids.push(id.clone());
+            }
+            *selector = Selector::Class(format!("id{}{}", prefix, id));
+        }
+        Selector::All => {
+            *all = true;
+            *selector = Selector::Class(format!("all{}", prefix));
+        }
+        Selector::Compound(parts) => {
+            for part in parts {
+                scope_selector(part, prefix, ids, classes, tags, all);
+            }
+        }
+        Selector::Descendant(a, b)
+        | Selector::Child(a, b)
+        | Selector::NextSibling(a, b)
+        | Selector::SubsequentSibling(a, b) => {
+            scope_selector(a, prefix, ids, classes, tags, all);
+            scope_selector(b, prefix, ids, classes, tags, all);
+        }
+        Selector::None | Selector::Pseduo(_) | Selector::Attribute(_) | Selector::Keyframe(_) => {}
+    }
+}
@@ -0,0 +1,224 @@
+use crate::{
+    css::{Rule, Selector, StyleSheet},
+    utils::children_of,
+    ClassList, Component, Dialect, Id, Node,
+};
+
+/// Properties understood by the GL target's `codegen_rules`.
+pub static GL_PROPERTIES: [&str; 6] = [
+    "background-color",
+    "border",
+    "color",
+    "font-size",
+    "font-family",
+    "alt",
+];
+
+#[rustfmt::skip]
+pub static KNOWN_PROPERTIES: &[&str] = &[
+    "accent-color", "align-content", "align-items", "align-self", "all", "animation",
+    "animation-delay", "animation-direction", "animation-duration", "animation-fill-mode",
+    "animation-iteration-count", "animation-name", "animation-play-state",
+    "animation-timing-function", "appearance", "aspect-ratio", "backdrop-filter",
+    "backface-visibility", "background", "background-attachment", "background-blend-mode",
+    "background-clip", "background-color", "background-image", "background-origin",
+    "background-position", "background-position-x", "background-position-y",
+    "background-repeat", "background-size", "block-size", "border", "border-block",
+    "border-block-end", "border-block-start", "border-bottom", "border-bottom-color",
+    "border-bottom-left-radius", "border-bottom-right-radius", "border-bottom-style",
+    "border-bottom-width", "border-collapse", "border-color", "border-image",
+    "border-image-outset", "border-image-repeat", "border-image-slice", "border-image-source",
+    "border-image-width", "border-inline", "border-inline-end", "border-inline-start",
+    "border-left", "border-left-color", "border-left-style", "border-left-width",
+    "border-radius", "border-right", "border-right-color", "border-right-style",
+    "border-right-width", "border-spacing", "border-style", "border-top", "border-top-color",
+    "border-top-left-radius", "border-top-right-radius", "border-top-style",
+    "border-top-width", "border-width", "bottom", "box-shadow", "box-sizing", "break-after",
+    "break-before", "break-inside", "caption-side", "caret-color", "clear", "clip",
+    "clip-path", "color", "color-scheme", "column-count", "column-fill", "column-gap",
+    "column-rule", "column-rule-color", "column-rule-style", "column-rule-width",
+    "column-span", "column-width", "columns", "contain", "container", "container-name",
+    "container-type", "content", "content-visibility", "counter-increment", "counter-reset",
+    "counter-set", "cursor", "direction", "display", "empty-cells", "fill", "fill-opacity",
+    "filter", "flex", "flex-basis", "flex-direction", "flex-flow", "flex-grow", "flex-shrink",
+    "flex-wrap", "float", "font", "font-display", "font-family", "font-feature-settings",
+    "font-kerning", "font-size", "font-size-adjust", "font-stretch", "font-style",
+    "font-variant", "font-variant-caps", "font-variant-numeric", "font-weight", "gap", "grid",
+    "grid-area", "grid-auto-columns", "grid-auto-flow", "grid-auto-rows", "grid-column",
+    "grid-column-end", "grid-column-start", "grid-row", "grid-row-end", "grid-row-start",
+    "grid-template", "grid-template-areas", "grid-template-columns", "grid-template-rows",
+    "height", "hyphens", "image-rendering", "inline-size", "inset", "inset-block",
+    "inset-inline", "isolation", "justify-content", "justify-items", "justify-self", "left",
+    "letter-spacing", "line-break", "line-height", "list-style", "list-style-image",
+    "list-style-position", "list-style-type", "margin", "margin-block", "margin-block-end",
+    "margin-block-start", "margin-bottom", "margin-inline", "margin-inline-end",
+    "margin-inline-start", "margin-left", "margin-right", "margin-top", "mask", "mask-image",
+    "max-block-size", "max-height", "max-inline-size", "max-width", "min-block-size",
+    "min-height", "min-inline-size", "min-width", "mix-blend-mode", "object-fit",
+    "object-position", "opacity", "order", "orphans", "outline", "outline-color",
+    "outline-offset", "outline-style", "outline-width", "overflow", "overflow-anchor",
+    "overflow-wrap", "overflow-x", "overflow-y", "overscroll-behavior", "padding",
+    "padding-block", "padding-block-end", "padding-block-start", "padding-bottom",
+    "padding-inline", "padding-inline-end", "padding-inline-start", "padding-left",
+    "padding-right", "padding-top", "page-break-after", "page-break-before",
+    "page-break-inside", "perspective", "perspective-origin", "place-content", "place-items",
+    "place-self", "pointer-events", "position", "quotes", "resize", "right", "rotate",
+    "row-gap", "scale", "scroll-behavior", "scroll-margin", "scroll-padding",
+    "scroll-snap-align", "scroll-snap-type", "scrollbar-color", "scrollbar-gutter",
+    "scrollbar-width", "shape-outside", "stroke", "stroke-dasharray", "stroke-dashoffset",
+    "stroke-linecap", "stroke-linejoin", "stroke-opacity", "stroke-width", "tab-size",
+    "table-layout", "text-align", "text-align-last", "text-decoration",
+    "text-decoration-color", "text-decoration-line", "text-decoration-style",
+    "text-decoration-thickness", "text-indent", "text-justify", "text-overflow",
+    "text-rendering", "text-shadow", "text-transform", "text-underline-offset", "top",
+    "touch-action", "transform", "transform-origin", "transform-style", "transition",
+    "transition-delay", "transition-duration", "transition-property",
+    "transition-timing-function", "translate", "unicode-bidi", "user-select",
+    "vertical-align", "visibility", "white-space", "widows", "width", "will-change",
+    "word-break", "word-spacing", "word-wrap", "writing-mode", "z-index", "zoom",
+];
+
+/// The ids, classes and tags used by a component's template. When any element sets an id or
+/// class list reactively the corresponding list can't be known at compile time.
+#[derive(Debug, Default)]
+struct TemplateUsage {
+    ids: Vec<String>,
+    reactive_ids: bool,
+    classes: Vec<String>,
+    reactive_classes: bool,
+    tags: Vec<String>,
+}
+
+/// Checks a component's (unscoped) styles against its template. Returns the warnings found and
+/// the stylesheet, with rules that can never match removed if `drop_dead_rules` is set.
+pub fn lint_component_css(
+    component: &Component,
+    styles: StyleSheet,
+    drop_dead_rules: bool,
+) -> (StyleSheet, Vec<String>) {
+    let mut usage = TemplateUsage::default();
+
+    collect_usage(&mut Node::Component(component.clone()), &mut usage);
+
+    let mut warnings = vec![];
+
+    for rule in &styles {
+        if let (Dialect::RsLilac, Some(at_rule)) = (&component.dialect, rule.at_rules.last()) {
+            warnings.push(format!(
+                "\"{}\" At-rules aren't supported on GL, \"{}\" is ignored.",
+                component.name, at_rule
+            ));
+            continue;
+        }
+
+        if component.dialect == Dialect::RsLilac && !matches!(rule.selector, Selector::Class(_)) {
+            warnings.push(format!(
+                "\"{}\" Only class selectors are supported on GL, \"{}\" is ignored.",
+                component.name,
+                rule.selector.codegen()
+            ));
+            continue;
+        }
+
+        // At-rules like `@font-face` have descriptors of their own rather than properties.
+        if !matches!(rule.selector, Selector::None) {
+            for prop in &rule.properties {
+                if !is_known_property(&prop.name, &component.dialect) {
+                    warnings.push(format!(
+                        "\"{}\" Unknown CSS property \"{}\" in rule \"{}\".",
+                        component.name,
+                        prop.name,
+                        rule.selector.codegen()
+                    ));
+                }
+            }
+        }
+
+        if let Some(reason) = never_matches(rule, &usage) {
+            warnings.push(format!(
+                "\"{}\" Unused CSS selector \"{}\", {}.",
+                component.name,
+                rule.selector.codegen(),
+                reason
+            ));
+        }
+    }
+
+    let styles = if drop_dead_rules {
+        styles
+            .into_iter()
+            .filter(|r| never_matches(r, &usage).is_none())
+            .collect()
+    } else {
+        styles
+    };
+
+    (styles, warnings)
+}
+
+fn is_known_property(name: &str, dialect: &Dialect) -> bool {
+    match dialect {
+        Dialect::RsLilac => GL_PROPERTIES.contains(&name),
+        Dialect::JsLilac | Dialect::TsLilac => {
+            // Custom and vendor prefixed properties are always allowed.
+            name.starts_with("-") || KNOWN_PROPERTIES.contains(&name)
+        }
+    }
+}
+
+/// Every class, id and tag in a selector is scoped to the component so if any of them isn't used
+/// by the template the rule can never match.
+fn never_matches(rule: &Rule, usage: &TemplateUsage) -> Option<String> {
+    selector_never_matches(&rule.selector, usage)
+}
+
+fn selector_never_matches(selector: &Selector, usage: &TemplateUsage) -> Option<String> {
+    match selector {
+        Selector::Class(c) if !usage.reactive_classes && !usage.classes.contains(c) => Some(
+            format!("no element in this component has the class \"{}\"", c),
+        ),
+        Selector::ID(id) if !usage.reactive_ids && !usage.ids.contains(id) => Some(format!(
+            "no element in this component has the id \"{}\"",
+            id
+        )),
+        Selector::Tag(t) if !usage.tags.contains(t) => {
+            Some(format!("this component has no <{}> elements", t))
+        }
+        Selector::Compound(parts) => parts.iter().find_map(|p| selector_never_matches(p, usage)),
+        Selector::Descendant(a, b)
+        | Selector::Child(a, b)
+        | Selector::NextSibling(a, b)
+        | Selector::SubsequentSibling(a, b) => {
+            selector_never_matches(a, usage).or_else(|| selector_never_matches(b, usage))
+        }
+        _ => None,
+    }
+}
+
+fn collect_usage(node: &mut Node, usage: &mut TemplateUsage) {
+    if let Node::Element(e) = node {
+        usage.tags.push(e.name.clone());
+
+        match &e.id {
+            Some(Id::Static(id)) => usage.ids.push(id.clone()),
+            Some(Id::Reactive(_)) => usage.reactive_ids = true,
+            None => {}
+        }
+
+        match &e.classes {
+            Some(ClassList::Static(classes)) => usage.classes.extend(classes.iter().cloned()),
+            Some(ClassList::Reactive(_)) => usage.reactive_classes = true,
+            None => {}
+        }
+
+        usage
+            .classes
+            .extend(e.class_directives.iter().map(|d| d.name.clone()));
+    }
+
+    for children in children_of(node) {
+        for child in children {
+            collect_usage(child, usage);
+        }
+    }
+}
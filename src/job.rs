@@ -1,14 +1,22 @@
-use std::{collections::HashMap, path::PathBuf, process::exit};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::exit,
+};
 
 use owo_colors::OwoColorize;
 
 use crate::{
     compile::fill_holes,
+    component_events::lint_component_events,
     config::load_config,
     css::{self, StyleSheet},
     css_component_scoping::scope_css_to_component,
+    css_lint::lint_component_css,
     js_codegen::codegen_stylesheet,
+    js_modules::{bundle_modules, is_module_path, resolve_component_imports, JsModule},
     parse::parse_full,
+    source_map::SourceFile,
     utils::children_of,
     Component, Dialect, Node,
 };
@@ -16,6 +24,7 @@ use crate::{
 pub struct Job {
     pub path: PathBuf,
     pub output: PathBuf,
+    pub release: bool,
 }
 
 pub enum Target {
@@ -49,15 +58,45 @@ fn compile(job: &Job) -> Result<(), String> {
 
     let files = list_files(&job.path).map_err(|e| e.to_string())?;
 
+    let (module_files, files): (Vec<_>, Vec<_>) = files
+        .into_iter()
+        .partition(|path| is_module_path(&project_path(&job.path, path)));
+
+    let modules = module_files
+        .iter()
+        .map(|path| {
+            let code = load_file(path).map_err(|_| "Unable to load file.".to_string())?;
+            JsModule::new(&project_path(&job.path, path), &code)
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let bundle = bundle_modules(&modules)?;
+
     let mut components_map = HashMap::new();
 
     let mut stylesheet = vec![];
 
+    let mut sources = HashMap::new();
+
     for path in files {
-        let component = load_componet(path).map_err(|e| e.to_string())?;
+        let dir = project_path(&job.path, path.parent().unwrap());
+
+        let source_path = path_from_output(&job.output, &path);
+
+        let (mut component, contents) = load_componet(path).map_err(|e| e.to_string())?;
+
+        if component.dialect != Dialect::RsLilac {
+            resolve_component_imports(&mut component, &dir, &modules)?;
+        }
 
         let (styles, component) = collect_css(component);
 
+        let (styles, warnings) = lint_component_css(&component, styles, job.release);
+
+        for warning in warnings {
+            println!("{}: {}", "WARN".yellow(), warning);
+        }
+
         let (styles, component) = scope_css_to_component(component, styles);
 
         stylesheet.extend(styles);
@@ -69,6 +108,13 @@ fn compile(job: &Job) -> Result<(), String> {
             ));
         }
 
+        sources.insert(
+            component.name.clone(),
+            SourceFile {
+                path: source_path,
+                contents,
+            },
+        );
         components_map.insert(component.name.clone(), component);
     }
 
@@ -88,11 +134,15 @@ fn compile(job: &Job) -> Result<(), String> {
         Dialect::RsLilac => Target::GL,
     };
 
+    for warning in lint_component_events(&components_map) {
+        println!("{}: {}", "WARN".yellow(), warning);
+    }
+
     let mut root_node = Node::Component(root.clone());
 
     fill_holes(&mut root_node, &components_map).map_err(|e| e.to_string())?;
 
-    let code = root_node.codegen(target, stylesheet)?;
+    let code = root_node.codegen(target, stylesheet, &bundle, &sources, job.release)?;
 
     write_file(&job.output, &code).map_err(|e| e.to_string())?;
 
@@ -110,7 +160,7 @@ fn write_file(path: &PathBuf, contents: &str) -> Result<(), std::io::Error> {
 fn list_files(path: &PathBuf) -> Result<Vec<PathBuf>, std::io::Error> {
     let mut files = vec![];
 
-    const EXTENSIONS: [&str; 4] = ["lilac", "rslilac", "tslilac", "jslilac"];
+    const EXTENSIONS: [&str; 6] = ["lilac", "rslilac", "tslilac", "jslilac", "js", "ts"];
 
     for entry in std::fs::read_dir(path)? {
         let entry = entry?;
@@ -121,7 +171,7 @@ fn list_files(path: &PathBuf) -> Result<Vec<PathBuf>, std::io::Error> {
             files.push(path.clone());
         }
 
-        if path.is_dir() {
+        if path.is_dir() && !path.ends_with("node_modules") {
             files.extend(list_files(&path)?);
         }
     }
@@ -129,6 +179,44 @@ fn list_files(path: &PathBuf) -> Result<Vec<PathBuf>, std::io::Error> {
     Ok(files)
 }
 
+/// The path of a file relative to the project root, with `/` separators.
+fn project_path(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The path of a file relative to the directory the output is written to, which is how its source
+/// maps find it.
+fn path_from_output(output: &Path, path: &Path) -> String {
+    let dir = match output.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let (Ok(dir), Ok(path)) = (std::fs::canonicalize(dir), std::fs::canonicalize(path)) else {
+        return path.to_string_lossy().replace('\\', "/");
+    };
+
+    let common = dir
+        .components()
+        .zip(path.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let ups = dir.components().skip(common).map(|_| "..".into());
+    let downs = path
+        .components()
+        .skip(common)
+        .map(|c| c.as_os_str().to_string_lossy());
+
+    ups.chain(downs).collect::<Vec<_>>().join("/")
+}
+
 fn get_root_css(path: &PathBuf) -> Result<String, String> {
     let path = path.join("root.css");
 
@@ -156,7 +244,7 @@ fn _collect_css(node: &mut Node) -> StyleSheet {
         ss.extend(css.clone());
     }
 
-    if let Some(children) = children_of(node) {
+    for children in children_of(node) {
         for i in (0..children.len()).rev() {
             let node = &mut children[i];
             ss.extend(_collect_css(node));
@@ -168,7 +256,8 @@ fn _collect_css(node: &mut Node) -> StyleSheet {
     ss
 }
 
-fn load_componet(path: PathBuf) -> Result<Component, String> {
+/// The component in a file, along with the file's contents.
+fn load_componet(path: PathBuf) -> Result<(Component, String), String> {
     let contents = load_file(&path).map_err(|_| "Unable to load file.".to_string())?;
 
     let name = path.file_stem().unwrap().to_str().unwrap();
@@ -186,5 +275,8 @@ fn load_componet(path: PathBuf) -> Result<Component, String> {
         _ => unreachable!(),
     };
 
-    parse_full(&contents, name, dialect).map_err(|err| err.format(name, &contents))
+    let component =
+        parse_full(&contents, name, dialect).map_err(|err| err.format(name, &contents))?;
+
+    Ok((component, contents))
 }
@@ -4,12 +4,19 @@ use owo_colors::colors::xterm::PompadourMagenta;
 
 use crate::{
     codegen::CodegenResult,
-    css::{Rule, Selector, StyleSheet},
-    js_component_scoping::ComponentVariableRenamer,
+    component_events::component_events,
+    css::{value_to_css, Rule, Selector, StyleSheet},
+    js_component_scoping::{top_level_declarations, ComponentVariableRenamer},
+    minify::minify_html,
     parse::VOID_ELEMENTS,
-    utils::find_and_replace_js_identifiers,
-    Attribute, ClassList, Component, Dialect, Element, Id, JSExpression, Node, ReactiveAttribute,
-    ScriptTag, StaticAttribute, ID_COUNTER,
+    source_map::{attach_source_maps, mark, strip_markers, SourceFile},
+    utils::{
+        children_of, encode_html_attribute, encode_html_text, find_and_replace_js_identifiers,
+        js_string_literal,
+    },
+    Attribute, AwaitBranch, ClassList, Component, Dialect, Element, Id, JSExpression, Node,
+    ReactiveAttribute, ScriptTag, Span, SpreadAttribute, StaticAttribute, Transition,
+    TransitionKind, ID_COUNTER,
 };
 
 type CVR = ComponentVariableRenamer;
@@ -32,7 +39,13 @@ impl CodegenType {
 type RRM = HashMap<String, JSExpression>;
 
 impl Node {
-    pub fn full_js_codegen(&self, stylesheet: StyleSheet) -> Result<String, String> {
+    pub fn full_js_codegen(
+        &self,
+        stylesheet: StyleSheet,
+        modules: &str,
+        sources: &HashMap<String, SourceFile>,
+        release: bool,
+    ) -> Result<String, String> {
         let mut root_cvr = CVR::new(&"root".to_string());
         let rrm = RRM::new();
 
@@ -40,20 +53,44 @@ impl Node {
 
         let html = self.codegen_js(&_type, &root_cvr, rrm)?;
 
-        Ok(format!(
+        let module_scripts = module_scripts_codegen(&mut self.clone(), &mut vec![]);
+
+        let prelude = if release {
+            include_str!("../prelude.js").replacen(
+                "const __RELEASE = false;",
+                "const __RELEASE = true;",
+                1,
+            )
+        } else {
+            include_str!("../prelude.js").to_string()
+        };
+
+        let document = format!(
             "<!DOCTYPE html>
 <!-- This file was generated by Lilac v{}. -->
 <head>
     <meta charset=\"UTF-8\">
 </head>
 <script>{}</script>
-<style>{}</style>
+{}{}<style>{}</style>
 {}",
             env!("CARGO_PKG_VERSION"),
-            include_str!("../prelude.js"),
+            prelude,
+            if modules.is_empty() {
+                "".to_string()
+            } else {
+                format!("<script>\n{modules}</script>\n")
+            },
+            module_scripts,
             codegen_stylesheet(&stylesheet),
             html
-        ))
+        );
+
+        Ok(if release {
+            minify_html(&strip_markers(&document), &top_level_declarations(&prelude))
+        } else {
+            attach_source_maps(&document, sources)
+        })
     }
 
     fn codegen_js(&self, _type: &CodegenType, cvr: &CVR, rrm: RRM) -> CodegenResult {
@@ -61,32 +98,37 @@ impl Node {
             Node::Component(c) => c.codegen(_type, cvr, rrm)?,
             Node::Element(e) => e.codegen(_type, cvr, rrm)?,
             Node::Text(t) => match _type {
-                CodegenType::HTML { .. } => t.clone(),
+                CodegenType::HTML { .. } => encode_html_text(t),
                 CodegenType::JSDom {
                     parent_elem_var_name,
                 } => {
                     let text_node_var_name =
                         format!("__textNode{}", ID_COUNTER.fetch_add(1, Ordering::SeqCst));
                     format!(
-                        "const {} = document.createTextNode(`{}`);\n",
-                        text_node_var_name, t
+                        "const {} = document.createTextNode({});\n",
+                        text_node_var_name,
+                        js_string_literal(t)
                     ) + &format!(
                         "{}.appendChild({});\n",
                         parent_elem_var_name, text_node_var_name
                     )
                 }
             },
-            Node::ReactiveText(t) => reactive_text_codegen(t, _type, cvr),
+            Node::ReactiveText(t, span) => reactive_text_codegen(t, span, _type, cvr),
             Node::Loop {
                 iterator_variable,
+                index_variable,
                 reactive_list,
                 iteratable,
                 children,
+                empty,
             } => loop_codegen(
                 *reactive_list,
                 iterator_variable,
+                index_variable,
                 iteratable,
                 children,
+                empty,
                 _type,
                 cvr,
                 rrm,
@@ -95,6 +137,17 @@ impl Node {
                 condition,
                 children,
             } => conditional_elements_codegen(condition, children, _type, cvr, rrm)?,
+            Node::Key { key, children } => key_codegen(key, children, _type, cvr, rrm)?,
+            Node::ErrorBoundary {
+                fallback, children, ..
+            } => error_boundary_codegen(fallback, children, _type, cvr, rrm)?,
+            Node::Await {
+                promise,
+                pending,
+                then,
+                catch,
+            } => await_codegen(promise, pending, then, catch, _type, cvr, rrm)?,
+            Node::Const { name, value } => const_codegen(name, value, _type, cvr)?,
             Node::ComponentHole { name, props, .. } => {
                 // HACK: These should generally be removed but when a component instance has no
                 // children supplied these get left behind.
@@ -103,7 +156,8 @@ impl Node {
                 }
 
                 if let Some(create_fn_name) = rrm.get(name) {
-                    let props_set = codegen_props_set(&props, cvr);
+                    let props_set =
+                        codegen_props_set(&props, cvr) + &codegen_dispatcher(props, cvr);
 
                     let parent_elem_var_name = match _type {
                         CodegenType::JSDom {
@@ -112,7 +166,7 @@ impl Node {
                         _ => panic!(),
                     };
 
-                    format!("{{ {props_set};\n {create_fn_name}(props, {parent_elem_var_name}); }}")
+                    format!("{{ {props_set};\n {create_fn_name}(props, {parent_elem_var_name}, dispatch); }}")
                 } else {
                     panic!("Component {} not replaced.", name);
                 }
@@ -123,8 +177,40 @@ impl Node {
     }
 }
 
+/// The `<script context="module">` of every component used, emitted once per component.
+fn module_scripts_codegen(node: &mut Node, emitted: &mut Vec<String>) -> String {
+    let mut code = String::new();
+
+    if let Node::Component(c) = node {
+        if !emitted.contains(&c.name) {
+            emitted.push(c.name.clone());
+
+            let cvr = CVR::new(&c.name);
+
+            for child in &c.children {
+                if let Node::ScriptTag(script) = child {
+                    if script.is_module_context() {
+                        code.push_str(&format!(
+                            "<script>\n{}\n</script>\n",
+                            cvr.process_module(&script.code)
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    for children in children_of(node) {
+        for child in children {
+            code.push_str(&module_scripts_codegen(child, emitted));
+        }
+    }
+
+    code
+}
+
 fn script_tag_to_element(tag: &ScriptTag, var_renamer: &CVR) -> Element {
-    let code = var_renamer.process(&tag.code.clone());
+    let code = var_renamer.process(&mark(&tag.span, &tag.code));
     let attributes = tag.attributes.clone();
 
     let children = vec![Node::Text(code)];
@@ -132,7 +218,11 @@ fn script_tag_to_element(tag: &ScriptTag, var_renamer: &CVR) -> Element {
     Element {
         name: "script".to_string(),
         id: None,
+        id_classes: vec![],
         classes: None,
+        class_directives: vec![],
+        style_directives: vec![],
+        transitions: vec![],
         attributes,
         children,
     }
@@ -140,10 +230,21 @@ fn script_tag_to_element(tag: &ScriptTag, var_renamer: &CVR) -> Element {
 
 impl ScriptTag {
     fn codegen(&self, _type: &CodegenType, cvr: &CVR, rrm: RRM) -> CodegenResult {
+        // Module scripts are emitted once per component by `module_scripts_codegen`.
+        if self.is_module_context() {
+            return Ok("".to_string());
+        }
+
         Ok(match _type {
             CodegenType::HTML => script_tag_to_element(self, cvr).codegen(_type, cvr, rrm)?,
+            // Renames the module script's identifiers and any instance ones `cvr` has collected.
+            // The declarations of this script aren't collected, they're scoped by the block
+            // function it ends up in.
             CodegenType::JSDom { .. } => {
-                format!("{};\n", self.code.clone())
+                format!(
+                    "{};\n",
+                    cvr.process_no_declared(&mark(&self.span, &self.code))
+                )
             }
         })
     }
@@ -181,6 +282,8 @@ impl Element {
         assert!(_type.is_html());
 
         if has_reactive_attributes(&self.attributes)
+            || !self.class_directives.is_empty()
+            || !self.style_directives.is_empty()
             || self.id.as_ref().map(|id| id.is_reactive()).unwrap_or(false)
             || self
                 .classes
@@ -192,14 +295,13 @@ impl Element {
                 "__elem_reactive_html_{}",
                 ID_COUNTER.fetch_add(1, Ordering::SeqCst)
             );
-            let id = format!("r{}", ID_COUNTER.fetch_add(1, Ordering::SeqCst));
             let js = self.jsdom_codegen(&elem_var_name, _type, cvr, rrm)?;
 
             return Ok(format!(
-                "<span id=\"{id}\"></span>
-                <script>
-                    const {elem_var_name} = document.getElementById(\"{id}\");
+                "<script>
+                    const {elem_var_name} = document.createDocumentFragment();
                     {js}
+                    document.currentScript.replaceWith({elem_var_name});
                 </script>",
             ));
         }
@@ -209,7 +311,7 @@ impl Element {
         if let Some(id) = &self.id {
             match id {
                 Id::Static(id) => {
-                    code.push_str(&format!(" id=\"{}\"", id));
+                    code.push_str(&format!(" id=\"{}\"", encode_html_attribute(id)));
                 }
                 Id::Reactive(_) => {
                     panic!("Should have fallen back to JS Dom codegen.");
@@ -220,7 +322,10 @@ impl Element {
         if let Some(classes) = &self.classes {
             match classes {
                 ClassList::Static(classes) => {
-                    code.push_str(&format!(" class=\"{}\"", classes.join(" ")));
+                    code.push_str(&format!(
+                        " class=\"{}\"",
+                        encode_html_attribute(&classes.join(" "))
+                    ));
                 }
                 ClassList::Reactive(_) => {
                     panic!("Should have fallen back to JS Dom codegen.");
@@ -232,14 +337,17 @@ impl Element {
             match attr {
                 Attribute::Static(StaticAttribute { name, value }) => {
                     if let Some(value) = value {
-                        code.push_str(&format!(" {}=\"{}\"", name, value));
+                        code.push_str(&format!(" {}=\"{}\"", name, encode_html_attribute(value)));
                     } else {
                         code.push_str(&format!(" {}", name));
                     }
                 }
-                Attribute::Reactive(ReactiveAttribute { name, value }) => {
+                Attribute::Reactive(_) => {
                     panic!();
                 }
+                Attribute::Spread(_) => {
+                    panic!("Should have fallen back to JS Dom codegen.");
+                }
             }
         }
         code.push('>');
@@ -249,7 +357,11 @@ impl Element {
         }
 
         for child in &self.children {
-            code.push_str(&child.codegen_js(_type, cvr, rrm.clone())?);
+            match child {
+                // The contents of scripts aren't HTML so mustn't be encoded.
+                Node::Text(t) if self.name == "script" => code.push_str(t),
+                _ => code.push_str(&child.codegen_js(_type, cvr, rrm.clone())?),
+            }
         }
         code.push_str(&format!("</{}>", self.name));
 
@@ -270,27 +382,16 @@ impl Element {
             elem_var_name, self.name
         );
 
-        if let Some(id) = &self.id {
-            match id {
-                Id::Static(id) => {
-                    code.push_str(&format!("{elem_var_name}.id = \"{id}\";\n"));
-                }
-                Id::Reactive(expr) => {
-                    let update_fn_var_name =
-                        format!("__{}update_id", ID_COUNTER.fetch_add(1, Ordering::SeqCst));
-                    let r = reactive_expression(&expr, &update_fn_var_name, cvr);
-                    code.push_str(&format!(
-                        "const {update_fn_var_name} = (val) => {elem_var_name}.id = val;\n{r}\n"
-                    ));
-                }
-            }
-        }
+        let has_spread = self
+            .attributes
+            .iter()
+            .any(|a| matches!(a, Attribute::Spread(_)));
 
         if let Some(classes) = &self.classes {
             match classes {
                 ClassList::Static(classes) => {
-                    let classes = classes.join(" ");
-                    code.push_str(&format!("{elem_var_name}.className = \"{classes}\";\n"));
+                    let classes = js_string_literal(&classes.join(" "));
+                    code.push_str(&format!("{elem_var_name}.className = {classes};\n"));
                 }
                 ClassList::Reactive(expr) => {
                     let update_fn_var_name = format!(
@@ -298,22 +399,104 @@ impl Element {
                         ID_COUNTER.fetch_add(1, Ordering::SeqCst)
                     );
                     let r = reactive_expression(&expr, &update_fn_var_name, cvr);
+
+                    if self.class_directives.is_empty() && self.id_classes.is_empty() && !has_spread {
+                        code.push_str(&format!(
+                            "const {update_fn_var_name} = (val) => {elem_var_name}.className = val;\n{r}\n"
+                        ));
+                    } else {
+                        // Setting className would remove the classes toggled by directives and
+                        // reactive ids or added by spreads.
+                        code.push_str(&format!(
+                            "let {update_fn_var_name}_prev = [];
+                            const {update_fn_var_name} = (val) => {{
+                                {elem_var_name}.classList.remove(...{update_fn_var_name}_prev);
+                                {update_fn_var_name}_prev = val.split(\" \").filter((c) => c !== \"\");
+                                {elem_var_name}.classList.add(...{update_fn_var_name}_prev);
+                            }};\n{r}\n"
+                        ));
+                    }
+                }
+            }
+        }
+
+        // After the classes, so setting `className` doesn't remove the ones the id toggles.
+        if let Some(id) = &self.id {
+            match id {
+                Id::Static(id) => {
+                    let id = js_string_literal(id);
+                    code.push_str(&format!("{elem_var_name}.id = {id};\n"));
+                }
+                Id::Reactive(expr) => {
+                    let update_fn_var_name =
+                        format!("__{}update_id", ID_COUNTER.fetch_add(1, Ordering::SeqCst));
+                    let r = reactive_expression(&expr, &update_fn_var_name, cvr);
+                    let toggles: String = self
+                        .id_classes
+                        .iter()
+                        .map(|(id, class)| {
+                            format!(
+                                "{elem_var_name}.classList.toggle(\"{class}\", val === {});\n",
+                                js_string_literal(id)
+                            )
+                        })
+                        .collect();
                     code.push_str(&format!(
-                        "const {update_fn_var_name} = (val) => {elem_var_name}.className = val;\n{r}\n"
+                        "const {update_fn_var_name} = (val) => {{\n{elem_var_name}.id = val;\n{toggles}}};\n{r}\n"
                     ));
                 }
             }
         }
 
-        for attr in &self.attributes {
-            handle_attr(
-                attr,
+        for directive in &self.class_directives {
+            let update_fn_var_name = format!(
+                "__{}update_class_directive",
+                ID_COUNTER.fetch_add(1, Ordering::SeqCst)
+            );
+            let r = reactive_expression(&directive.value, &update_fn_var_name, cvr);
+            code.push_str(&format!(
+                "const {update_fn_var_name} = (val) => {elem_var_name}.classList.toggle(\"{}\", !!val);\n{r}\n",
+                directive.name
+            ));
+        }
+
+        for directive in &self.style_directives {
+            let update_fn_var_name = format!(
+                "__{}update_style_directive",
+                ID_COUNTER.fetch_add(1, Ordering::SeqCst)
+            );
+            let r = reactive_expression(&directive.value, &update_fn_var_name, cvr);
+            code.push_str(&format!(
+                "const {update_fn_var_name} = (val) => {{
+                    if (val === null || val === undefined || val === false) {elem_var_name}.style.removeProperty(\"{name}\");
+                    else {elem_var_name}.style.setProperty(\"{name}\", val);
+                }};\n{r}\n",
+                name = directive.name
+            ));
+        }
+
+        if has_spread {
+            code.push_str(&attribute_layers_codegen(
+                &self.attributes,
                 &elem_var_name,
-                &mut code,
                 cvr,
                 &self.name,
-                self.attributes.clone(),
-            );
+            ));
+        } else {
+            for attr in &self.attributes {
+                handle_attr(
+                    attr,
+                    &elem_var_name,
+                    &mut code,
+                    cvr,
+                    &self.name,
+                    self.attributes.clone(),
+                );
+            }
+        }
+
+        if !self.transitions.is_empty() {
+            code.push_str(&transitions_codegen(&self.transitions, &elem_var_name, cvr));
         }
 
         let _type = CodegenType::JSDom {
@@ -333,6 +516,31 @@ impl Element {
     }
 }
 
+/// Registers the element's intro and outro, the last directive for each wins.
+fn transitions_codegen(transitions: &[Transition], elem_var_name: &str, cvr: &CVR) -> String {
+    let find = |kind: TransitionKind| {
+        transitions
+            .iter()
+            .rev()
+            .find(|t| t.kind == kind || t.kind == TransitionKind::Both)
+            .map(|t| {
+                let params = t.params.as_deref().unwrap_or("{}");
+                format!(
+                    "{{ fn: {}, params: ({}) }}",
+                    cvr.process_no_declared(&t.name),
+                    cvr.process_no_declared(&params.to_string())
+                )
+            })
+            .unwrap_or("null".to_string())
+    };
+
+    format!(
+        "__transition({elem_var_name}, {}, {});\n",
+        find(TransitionKind::In),
+        find(TransitionKind::Out)
+    )
+}
+
 fn handle_attr(
     attr: &Attribute,
     elem_var_name: &String,
@@ -344,8 +552,9 @@ fn handle_attr(
     match attr {
         Attribute::Static(StaticAttribute { name, value }) => {
             if let Some(value) = value {
+                let value = js_string_literal(value);
                 code.push_str(&format!(
-                    "{elem_var_name}.setAttribute(\"{name}\", \"{value}\");\n",
+                    "{elem_var_name}.setAttribute(\"{name}\", {value});\n",
                 ));
             } else {
                 code.push_str(&format!(
@@ -353,7 +562,9 @@ fn handle_attr(
                 ));
             }
         }
-        Attribute::Reactive(ReactiveAttribute { name, value }) => {
+        Attribute::Reactive(ReactiveAttribute { name, value, span }) => {
+            let value = &mark(span, value);
+
             if handle_special_attr(
                 attr,
                 elem_var_name,
@@ -376,9 +587,79 @@ fn handle_attr(
                         "const {update_fn_var_name} = (val) => {elem_var_name}.setAttribute(\"{name}\", val);\n{r}\n",
                     ));
         }
+        Attribute::Spread(_) => unreachable!("Spreads are applied by attribute_layers_codegen."),
     }
 }
 
+/// Applies the attributes of an element with spreads through `__attribute_layers`, so every
+/// update respects their order. `onclick`, `ref` and `bind` aren't attributes, so they're still
+/// handled on their own.
+fn attribute_layers_codegen(
+    attributes: &[Attribute],
+    elem_var_name: &String,
+    cvr: &CVR,
+    elem_name: &String,
+) -> String {
+    let layers_var_name = format!("{elem_var_name}_attributes");
+
+    let mut code = format!(
+        "const {layers_var_name} = __attribute_layers({elem_var_name}, {});\n",
+        attributes.len()
+    );
+
+    for (i, attr) in attributes.iter().enumerate() {
+        match attr {
+            Attribute::Static(StaticAttribute { name, value }) => {
+                let value = value
+                    .as_deref()
+                    .map_or("true".to_string(), js_string_literal);
+                code.push_str(&format!(
+                    "{layers_var_name}({i}, {{ {}: {value} }});\n",
+                    js_string_literal(name)
+                ));
+            }
+            Attribute::Reactive(ReactiveAttribute { name, value, span }) => {
+                let value = &mark(span, value);
+
+                if handle_special_attr(
+                    attr,
+                    elem_var_name,
+                    &mut code,
+                    cvr,
+                    elem_name,
+                    attributes.to_vec(),
+                    name,
+                    value,
+                ) {
+                    continue;
+                }
+
+                let update_fn_var_name =
+                    format!("__{}update_attr", ID_COUNTER.fetch_add(1, Ordering::SeqCst));
+                let r = reactive_expression(value, &update_fn_var_name, cvr);
+
+                code.push_str(&format!(
+                    "const {update_fn_var_name} = (val) => {layers_var_name}({i}, {{ {}: val }});\n{r}\n",
+                    js_string_literal(name)
+                ));
+            }
+            Attribute::Spread(SpreadAttribute { value }) => {
+                let update_fn_var_name = format!(
+                    "__{}update_spread",
+                    ID_COUNTER.fetch_add(1, Ordering::SeqCst)
+                );
+                let r = reactive_expression(value, &update_fn_var_name, cvr);
+
+                code.push_str(&format!(
+                    "const {update_fn_var_name} = (val) => {layers_var_name}({i}, val);\n{r}\n"
+                ));
+            }
+        }
+    }
+
+    code
+}
+
 fn handle_special_attr(
     attr: &Attribute,
     elem_var_name: &String,
@@ -394,6 +675,20 @@ fn handle_special_attr(
         let c = format!("{elem_var_name}.onclick = ({value});");
         code.push_str(&c);
         return true;
+    } else if name == "ref" {
+        let value = cvr.process_no_declared(value);
+
+        // A state is set to the element and back to null once it's unmounted, anything else is
+        // just assigned to.
+        code.push_str(&format!(
+            "if (({value})?.__STATE === true) {{
+                ({value}).set(() => {elem_var_name});
+                unmount(() => ({value}).set(() => null));
+            }} else {{
+                {value} = {elem_var_name};
+            }}\n"
+        ));
+        return true;
     } else if name == "bind" {
         let value = cvr.process_no_declared(&value);
 
@@ -469,17 +764,24 @@ fn codegen_props_set(props: &Vec<Attribute>, cvr: &CVR) -> JSExpression {
         .map(|p| match p {
             Attribute::Static(StaticAttribute { name, value }) => {
                 if let Some(value) = value {
-                    format!("props.{} = \"{}\";", name, value)
+                    format!(
+                        "props{} = {};",
+                        prop_accessor(name),
+                        js_string_literal(value)
+                    )
                 } else {
-                    format!("props.{} = true;", name)
+                    format!("props{} = true;", prop_accessor(name))
                 }
             }
-            Attribute::Reactive(ReactiveAttribute { name, value }) => {
-                let mut expr = value.to_string();
+            Attribute::Reactive(ReactiveAttribute { name, value, span }) => {
+                let expr = cvr.process_no_declared(&mark(span, value));
 
-                expr = cvr.process_no_declared(&expr);
+                format!("props{} = {};", prop_accessor(name), expr)
+            }
+            Attribute::Spread(SpreadAttribute { value }) => {
+                let expr = cvr.process_no_declared(value);
 
-                format!("props.{} = {};", name, expr)
+                format!("Object.assign(props, {});", expr)
             }
         })
         .collect::<Vec<String>>()
@@ -488,6 +790,41 @@ fn codegen_props_set(props: &Vec<Attribute>, cvr: &CVR) -> JSExpression {
     format!("const props = {{}};\n{sets}\n")
 }
 
+/// The instance's `dispatch`, which calls the `on<event>` handler it was given for an event.
+fn codegen_dispatcher(props: &[Attribute], cvr: &CVR) -> JSExpression {
+    let handlers = props
+        .iter()
+        .filter_map(|p| match p {
+            Attribute::Reactive(ReactiveAttribute { name, value, span }) => {
+                let event = name.strip_prefix("on").filter(|e| !e.is_empty())?;
+                Some(format!(
+                    "{}: ({}),",
+                    js_string_literal(event),
+                    cvr.process_no_declared(&mark(span, value))
+                ))
+            }
+            _ => None,
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    format!("const dispatch = __dispatcher({{\n{handlers}\n}});\n")
+}
+
+/// Props with names that aren't valid identifiers, like `aria-label`, need bracket notation.
+fn prop_accessor(name: &str) -> String {
+    let is_identifier = name.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '$')
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '$');
+
+    if is_identifier {
+        format!(".{}", name)
+    } else {
+        format!("[\"{}\"]", name)
+    }
+}
+
 impl Component {
     fn codegen(&self, _type: &CodegenType, cvr: &CVR, mut rrm: RRM) -> CodegenResult {
         if self.dialect != Dialect::JsLilac && self.dialect != Dialect::TsLilac {
@@ -516,25 +853,40 @@ impl Component {
 
         let mut props_set = codegen_props_set(&self.props, cvr);
 
+        if self.recursive || component_events(self).dispatches {
+            props_set.push_str(&codegen_dispatcher(&self.props, cvr));
+        }
+
         let child_cvr = CVR::new(&self.name);
 
+        for child in &self.children {
+            if let Node::ScriptTag(script) = child {
+                if script.is_module_context() {
+                    child_cvr.process_module(&script.code);
+                }
+            }
+        }
+
         if _type.is_html() {
             props_set = child_cvr.process(&props_set);
         }
 
+        let name = &self.name;
+
         let inner: String = self
             .children
             .iter()
             .map(|c| c.codegen_js(_type, &child_cvr, rrm.clone()))
             .collect::<CodegenResult>()?;
 
+        // Every instance gets a context of its own for `setContext`, inheriting from the one it's
+        // created in. In HTML its nodes are in the document once it's done, so its mount hooks can
+        // run, while blocks run them when inserting what they rendered.
         Ok(match _type {
             CodegenType::HTML { .. } => {
-                if props_set.is_empty() {
-                    inner
-                } else {
-                    format!("<script>\n{props_set}\n</script>\n{inner}")
-                }
+                format!(
+                    "<script>\n{props_set}\n__enter_context(\"{name}\");\n</script>\n{inner}<script>__exit_context();\n__flush_mounts();</script>\n"
+                )
             }
             CodegenType::JSDom {
                 parent_elem_var_name,
@@ -542,31 +894,38 @@ impl Component {
                 if self.recursive {
                     format!(
                         "
-                        const {create_fn_name} = (props, parent_elem) => {{
+                        const {create_fn_name} = (props, parent_elem, dispatch) => {{
                             const {parent_elem_var_name} = parent_elem;
+                            __enter_context(\"{name}\");
                             {inner}
+                            __exit_context();
                         }};
                         {{
                             {props_set};
-                            {create_fn_name}(props, {parent_elem_var_name});
+                            {create_fn_name}(props, {parent_elem_var_name}, dispatch);
                         }}
                         "
                     )
                 } else {
-                    format!("{{\n{props_set};{inner}}}\n")
+                    format!("{{\n{props_set};\n__enter_context(\"{name}\");\n{inner}__exit_context();\n}}\n")
                 }
             }
         })
     }
 }
 
-fn reactive_text_codegen(exp: &JSExpression, _type: &CodegenType, cvr: &CVR) -> String {
+fn reactive_text_codegen(
+    exp: &JSExpression,
+    span: &Span,
+    _type: &CodegenType,
+    cvr: &CVR,
+) -> String {
     let id = format!("r{}", ID_COUNTER.fetch_add(1, Ordering::SeqCst));
 
     let update_fn_var_name = format!("__{id}setTextNode");
     let text_node_var_name = format!("__{id}textNode");
 
-    let r = reactive_expression(exp, &update_fn_var_name, cvr);
+    let r = reactive_expression(&mark(span, exp), &update_fn_var_name, cvr);
 
     let js = format!(
         "
@@ -579,10 +938,9 @@ fn reactive_text_codegen(exp: &JSExpression, _type: &CodegenType, cvr: &CVR) ->
     match _type {
         CodegenType::HTML { .. } => {
             format!(
-                "<span id=\"{id}\"></span>
-<script>
+                "<script>
 {js}
-document.getElementById(\"{id}\").appendChild({text_node_var_name});
+document.currentScript.replaceWith({text_node_var_name});
 </script>"
             )
         }
@@ -597,6 +955,43 @@ document.getElementById(\"{id}\").appendChild({text_node_var_name});
     }
 }
 
+/// Declares a `{@const}` in the scope of the block it's in. A value with reactive dependencies
+/// becomes a state so the holes using it can subscribe to it with `$name`.
+fn const_codegen(
+    name: &str,
+    value: &JSExpression,
+    _type: &CodegenType,
+    cvr: &CVR,
+) -> CodegenResult {
+    if _type.is_html() {
+        return Err("{@const} is only allowed at the top of a block".to_string());
+    }
+
+    if !has_reactive_dependencies(value) {
+        let value = cvr.process_no_declared(value);
+        return Ok(format!("const {name} = ({value});\n"));
+    }
+
+    let update_fn_var_name = format!(
+        "__{}update_const",
+        ID_COUNTER.fetch_add(1, Ordering::SeqCst)
+    );
+    let r = reactive_expression(value, &update_fn_var_name, cvr);
+
+    Ok(format!(
+        "const {name} = state(undefined);
+        const {update_fn_var_name} = (val) => {name}.set(() => val);\n{r}\n"
+    ))
+}
+
+/// Whether an expression references any `$state`.
+fn has_reactive_dependencies(expr: &JSExpression) -> bool {
+    ress::Scanner::new(expr).any(|item| match item.map(|i| i.token) {
+        Ok(ress::tokens::Token::Ident(name)) => name.as_ref().starts_with('$'),
+        _ => false,
+    })
+}
+
 fn reactive_expression(expr: &JSExpression, update_fn: &JSExpression, cvr: &CVR) -> JSExpression {
     let id = format!("re{}", ID_COUNTER.fetch_add(1, Ordering::SeqCst));
 
@@ -697,26 +1092,37 @@ fn conditional_elements_codegen(
             if (c) {{
                 unmount(() => __run_unmounts(\"{elem_var_name}\"));
                 const __outer_rendering = __currently_rendering;
+                const __outer_context = __current_context;
+                __current_context = {id}context;
                 __currently_rendering = \"{elem_var_name}\";
+                const {elem_var_name} = document.createDocumentFragment();
                 {children}
+                __insert_before({elem_var_name}, {id}end);
+                __current_context = __outer_context;
                 __currently_rendering = __outer_rendering;
             }} else {{
-                {elem_var_name}.innerHTML = \"\";
+                __clear_between({id}start, {id}end);
                 __run_unmounts(\"{elem_var_name}\");
             }}
         }};"
     );
 
-    Ok(match _type {
-        CodegenType::HTML => {
-            let r = reactive_expression(condition, &format!("{}cond", id), cvr);
+    let r = reactive_expression(condition, &format!("{}cond", id), cvr);
+
+    Ok(block_container_codegen(&id, &format!("{func}\n{r}"), _type))
+}
 
+/// Places the two anchors a block renders its nodes between, followed by the block's code. The
+/// context the block is created in is kept for rendering its contents later.
+fn block_container_codegen(id: &str, code: &str, _type: &CodegenType) -> String {
+    match _type {
+        CodegenType::HTML => {
             format!(
-                "<span id=\"{id}\"></span>
-<script>
-    const {elem_var_name} = document.getElementById(\"{id}\");
-    {func}
-    {r} 
+                "<script>
+    const [{id}start, {id}end] = __anchors();
+    document.currentScript.replaceWith({id}start, {id}end);
+    const {id}context = __current_context;
+    {code}
 </script>
 "
             )
@@ -724,25 +1130,234 @@ fn conditional_elements_codegen(
         CodegenType::JSDom {
             parent_elem_var_name,
         } => {
-            let r = reactive_expression(condition, &format!("{}cond", id), cvr);
-
             format!(
                 "
-const {elem_var_name} = document.createElement(\"span\");
-{func}
-{r}
-{parent_elem_var_name}.appendChild({elem_var_name});
+const [{id}start, {id}end] = __anchors();
+{parent_elem_var_name}.appendChild({id}start);
+{parent_elem_var_name}.appendChild({id}end);
+const {id}context = __current_context;
+{code}
 "
             )
         }
-    })
+    }
+}
+
+fn key_codegen(
+    key: &JSExpression,
+    children: &[Node],
+    _type: &CodegenType,
+    cvr: &CVR,
+    rrm: RRM,
+) -> CodegenResult {
+    let id = format!("r{}", ID_COUNTER.fetch_add(1, Ordering::SeqCst));
+    let elem_var_name = format!("__elem{}", ID_COUNTER.fetch_add(1, Ordering::SeqCst));
+
+    let child_type = CodegenType::JSDom {
+        parent_elem_var_name: elem_var_name.clone(),
+    };
+
+    let children = children
+        .iter()
+        .map(|c| c.codegen_js(&child_type, cvr, rrm.clone()))
+        .collect::<CodegenResult>()?;
+
+    let func = format!(
+        "let {id}mounted = false;
+        let {id}previous;
+        unmount(() => __run_unmounts(\"{elem_var_name}\"));
+        const {id}key = (key) => {{
+            if ({id}mounted && {id}previous === key) return;
+
+            {id}mounted = true;
+            {id}previous = key;
+
+            __clear_between({id}start, {id}end);
+            __run_unmounts(\"{elem_var_name}\");
+
+            const __outer_rendering = __currently_rendering;
+            const __outer_context = __current_context;
+            __current_context = {id}context;
+            __currently_rendering = \"{elem_var_name}\";
+            const {elem_var_name} = document.createDocumentFragment();
+            {children}
+            __insert_before({elem_var_name}, {id}end);
+            __current_context = __outer_context;
+            __currently_rendering = __outer_rendering;
+        }};"
+    );
+
+    let r = reactive_expression(key, &format!("{}key", id), cvr);
+
+    Ok(block_container_codegen(&id, &format!("{func}\n{r}"), _type))
+}
+
+/// Creates the children right away, in a context pointing to the boundary so that what they throw
+/// later, while updating, is caught as well. A failure swaps them for the fallback.
+fn error_boundary_codegen(
+    fallback: &Option<JSExpression>,
+    children: &[Node],
+    _type: &CodegenType,
+    cvr: &CVR,
+    rrm: RRM,
+) -> CodegenResult {
+    let id = format!("r{}", ID_COUNTER.fetch_add(1, Ordering::SeqCst));
+    let elem_var_name = format!("__elem{}", ID_COUNTER.fetch_add(1, Ordering::SeqCst));
+
+    let child_type = CodegenType::JSDom {
+        parent_elem_var_name: elem_var_name.clone(),
+    };
+
+    let children = children
+        .iter()
+        .map(|c| c.codegen_js(&child_type, cvr, rrm.clone()))
+        .collect::<CodegenResult>()?;
+
+    let fallback = match fallback {
+        Some(fallback) => cvr.process_no_declared(fallback),
+        None => "undefined".to_string(),
+    };
+
+    let code = format!(
+        "const {id}boundary = __error_boundary({id}start, {id}end, \"{elem_var_name}\", ({fallback}));
+        unmount(() => __run_unmounts(\"{elem_var_name}\"));
+        {{
+            const __outer_rendering = __currently_rendering;
+            const __outer_context = __current_context;
+            const __outer_mounts = __pending_mounts.length;
+            __current_context = __boundary_context({id}context, {id}boundary);
+            __currently_rendering = \"{elem_var_name}\";
+            try {{
+                const {elem_var_name} = document.createDocumentFragment();
+                {children}
+                __insert_before({elem_var_name}, {id}end);
+            }} catch (error) {{
+                __pending_mounts.length = __outer_mounts;
+                {id}boundary.fail(error, __current_context);
+            }} finally {{
+                __current_context = __outer_context;
+                __currently_rendering = __outer_rendering;
+            }}
+        }}"
+    );
+
+    Ok(block_container_codegen(&id, &code, _type))
+}
+
+fn await_codegen(
+    promise: &JSExpression,
+    pending: &[Node],
+    then: &Option<AwaitBranch>,
+    catch: &Option<AwaitBranch>,
+    _type: &CodegenType,
+    cvr: &CVR,
+    rrm: RRM,
+) -> CodegenResult {
+    let id = format!("r{}", ID_COUNTER.fetch_add(1, Ordering::SeqCst));
+    let elem_var_name = format!("__elem{}", ID_COUNTER.fetch_add(1, Ordering::SeqCst));
+
+    let child_type = CodegenType::JSDom {
+        parent_elem_var_name: elem_var_name.clone(),
+    };
+
+    let branch_codegen = |children: &[Node]| {
+        children
+            .iter()
+            .map(|c| c.codegen_js(&child_type, cvr, rrm.clone()))
+            .collect::<CodegenResult>()
+    };
+
+    let bind_value = |branch: &AwaitBranch| match &branch.pattern {
+        Some(pattern) => format!("const {pattern} = __value;"),
+        None => "".to_string(),
+    };
+
+    let pending = branch_codegen(pending)?;
+
+    let then = match then {
+        Some(branch) => format!(
+            "{}\n{}",
+            bind_value(branch),
+            branch_codegen(&branch.children)?
+        ),
+        None => "".to_string(),
+    };
+
+    // Without a catch branch the rejection is rethrown rather than silently swallowed.
+    let catch = match catch {
+        Some(branch) => format!(
+            "{}\n{}",
+            bind_value(branch),
+            branch_codegen(&branch.children)?
+        ),
+        None => "throw __value;".to_string(),
+    };
+
+    // Every new promise bumps the token, so results of promises that have been replaced are
+    // ignored.
+    let func = format!(
+        "let {id}token = 0;
+        unmount(() => {{
+            {id}token++;
+            __run_unmounts(\"{elem_var_name}\");
+        }});
+        const {id}render = (branch, __value) => {{
+            __clear_between({id}start, {id}end);
+            __run_unmounts(\"{elem_var_name}\");
+
+            const __outer_rendering = __currently_rendering;
+            const __outer_context = __current_context;
+            __current_context = {id}context;
+            __currently_rendering = \"{elem_var_name}\";
+            const {elem_var_name} = document.createDocumentFragment();
+            try {{
+                if (branch === \"pending\") {{
+                    {pending}
+                }} else if (branch === \"then\") {{
+                    {then}
+                }} else {{
+                    {catch}
+                }}
+            }} finally {{
+                __current_context = __outer_context;
+                __currently_rendering = __outer_rendering;
+            }}
+            __insert_before({elem_var_name}, {id}end);
+        }};
+        const {id}await = (promise) => {{
+            const token = ++{id}token;
+
+            if (promise === null || typeof promise !== \"object\" || typeof promise.then !== \"function\") {{
+                {id}render(\"then\", promise);
+                return;
+            }}
+
+            {id}render(\"pending\");
+
+            promise.then(
+                (value) => {{
+                    if (token === {id}token) {id}render(\"then\", value);
+                }},
+                (error) => {{
+                    if (token === {id}token) {id}render(\"catch\", error);
+                }}
+            );
+        }};"
+    );
+
+    let r = reactive_expression(promise, &format!("{}await", id), cvr);
+
+    Ok(block_container_codegen(&id, &format!("{func}\n{r}"), _type))
 }
 
+#[allow(clippy::too_many_arguments)]
 fn loop_codegen(
     reactive_list: bool,
     iterator_variable: &String,
+    index_variable: &Option<String>,
     iteratable: &JSExpression,
     children: &[Node],
+    empty: &[Node],
     _type: &CodegenType,
     cvr: &CVR,
     rrm: RRM,
@@ -754,11 +1369,20 @@ fn loop_codegen(
         parent_elem_var_name: elem_var_name.clone(),
     };
 
+    let empty = empty
+        .iter()
+        .map(|c| c.codegen_js(&child_type, cvr, rrm.clone()))
+        .collect::<CodegenResult>()?;
+
     let children = if reactive_list {
         Node::Element(Element {
             name: "".to_string(),
             id: None,
+            id_classes: vec![],
             classes: None,
+            class_directives: vec![],
+            style_directives: vec![],
+            transitions: vec![],
             attributes: vec![],
             children: children.to_vec(),
         })
@@ -777,34 +1401,93 @@ fn loop_codegen(
         format!("const key = \"__key{id}\";")
     };
 
+    let indices_var_name = format!("__{id}indices");
+
+    // In reactive lists the index is a state, since inserting or removing earlier items moves
+    // the item along.
+    let bind_index = match index_variable {
+        Some(index) if reactive_list => format!(
+            "const {index} = state(position);
+            {indices_var_name}.splice(position, 0, {index});
+            for (let __j = position + 1; __j < {indices_var_name}.length; __j++) {{
+                const __index = __j;
+                {indices_var_name}[__j].set(() => __index);
+            }}"
+        ),
+        Some(index) => format!("const {index} = position;"),
+        None => "".to_string(),
+    };
+
+    let items_var_name = format!("__{id}items");
+
+    // Every item is followed by an anchor of its own, so an item's nodes are the ones between
+    // the previous item's anchor and its own.
     let add_item = format!(
         "
-        let wrapper_span = undefined;
         {{
             {set_key}
+            {bind_index}
 
-            const {elem_var_name} = document.createElement(\"span\");
+            const {elem_var_name} = document.createDocumentFragment();
             
             unmount(() => __run_unmounts(key));
             const __list_item_outer_rendering = __currently_rendering;
+            const __outer_context = __current_context;
+            __current_context = {id}context;
             __currently_rendering = key;
             {{
                 {children}
             }}
+            __current_context = __outer_context;
             __currently_rendering = __list_item_outer_rendering;
 
-            wrapper_span = {elem_var_name};
-        }}
-        if ({elem_var_name}.childNodes.length === 0) {{
-            {elem_var_name}.appendChild(wrapper_span);
-        }} else {{
-            {elem_var_name}.insertBefore(wrapper_span, {elem_var_name}.childNodes[position]);
+            const __item_end = document.createComment(\"\");
+            {elem_var_name}.appendChild(__item_end);
+            __insert_before({elem_var_name}, (position === 0 ? {id}start : {items_var_name}[position - 1]).nextSibling);
+            {items_var_name}.splice(position, 0, __item_end);
         }}"
     );
 
+    // The empty branch is rendered between the loop's anchors, which only ever hold it while there
+    // are no items.
+    let empty_key = format!("__empty{id}");
+
+    let toggle_empty = format!(
+        "let {id}empty_shown = false;
+        const {id}rendered_in = __currently_rendering;
+        const {id}show_empty = () => {{
+            if ({id}empty_shown) return;
+            {id}empty_shown = true;
+
+            const __outer_rendering = __currently_rendering;
+            const __outer_context = __current_context;
+            __current_context = {id}context;
+            __currently_rendering = {id}rendered_in;
+            unmount(() => __run_unmounts(\"{empty_key}\"));
+            __currently_rendering = \"{empty_key}\";
+            const {elem_var_name} = document.createDocumentFragment();
+            {empty}
+            __insert_before({elem_var_name}, {id}end);
+            __current_context = __outer_context;
+            __currently_rendering = __outer_rendering;
+        }};
+        const {id}hide_empty = () => {{
+            if (!{id}empty_shown) return;
+            {id}empty_shown = false;
+
+            __clear_between({id}start, {id}end);
+            __run_unmounts(\"{empty_key}\");
+        }};"
+    );
+
     let func = format!(
-        "const {id}loop = (arr) => {{
-        {elem_var_name}.innerHTML = \"\";
+        "const {indices_var_name} = [];
+        const {items_var_name} = [];
+        {toggle_empty}
+        const {id}loop = (arr) => {{
+        {id}hide_empty();
+        __clear_between({id}start, {id}end);
+        {items_var_name}.length = 0;
         for (let __i = 0; __i < arr.length; __i++) {{
             const {iterator_variable} = arr[__i];
             
@@ -812,12 +1495,16 @@ fn loop_codegen(
 
             {add_item}
         }}
+        if (arr.length === 0) {id}show_empty();
     }};"
     );
 
     let not_lstate_err = throw_rt_error("$lstate can only be used with an lstate type.");
 
-    let temp_currently_rendering_var_name = format!("__list_rendered_in_{}", ID_COUNTER.fetch_add(1, Ordering::SeqCst));
+    let temp_currently_rendering_var_name = format!(
+        "__list_rendered_in_{}",
+        ID_COUNTER.fetch_add(1, Ordering::SeqCst)
+    );
     let unsub_add_var_name = format!("__unsub_add_{}", ID_COUNTER.fetch_add(1, Ordering::SeqCst));
     let unsub_rm_var_name = format!("__unsub_rm_{}", ID_COUNTER.fetch_add(1, Ordering::SeqCst));
 
@@ -828,19 +1515,30 @@ fn loop_codegen(
             if ({list}.__LSTATE !== true) {not_lstate_err}
 
             const {temp_currently_rendering_var_name} = __currently_rendering;
-            const {unsub_add_var_name} = {list}.subscribeAdd((value, position) => {{
+            const {unsub_add_var_name} = {list}.subscribeAdd((__value, position) => {{
                 const __outer_rendering = __currently_rendering;
                 __currently_rendering = {temp_currently_rendering_var_name};
 
-                const {iterator_variable} = value;
+                const {iterator_variable} = __value;
 
+                {id}hide_empty();
                 {add_item}
 
                 __currently_rendering = __outer_rendering;
             }});
 
             const {unsub_rm_var_name} = {list}.subscribeRemove((position) => {{
-                {elem_var_name}.removeChild({elem_var_name}.childNodes[position]);
+                const __item_start = position === 0 ? {id}start : {items_var_name}[position - 1];
+                __clear_between(__item_start, {items_var_name}[position]);
+                {items_var_name}[position].remove();
+                {items_var_name}.splice(position, 1);
+                if ({items_var_name}.length === 0) {id}show_empty();
+
+                {indices_var_name}.splice(position, 1);
+                for (let __j = position; __j < {indices_var_name}.length; __j++) {{
+                    const __index = __j;
+                    {indices_var_name}[__j].set(() => __index);
+                }}
             }});
 
             unmount(() => {{
@@ -855,53 +1553,73 @@ fn loop_codegen(
         reactive_expression(iteratable, &format!("{}loop", id), cvr)
     };
 
-    Ok(match _type {
-        CodegenType::HTML => {
-            format!(
-                "<span id=\"{id}\"></span>
-                <script>
-                    const {elem_var_name} = document.getElementById(\"{id}\");
-                    
-                    {func}
-                    {instantiate_and_subscribe} 
-                </script>"
-            )
-        }
-        CodegenType::JSDom {
-            parent_elem_var_name,
-        } => {
-            format!(
-                "
-                const {elem_var_name} = document.createElement(\"span\");
-                {func}
-                {instantiate_and_subscribe}
-                {parent_elem_var_name}.appendChild({elem_var_name});
-                "
-            )
-        }
-    })
+    Ok(block_container_codegen(
+        &id,
+        &format!("{func}\n{instantiate_and_subscribe}"),
+        _type,
+    ))
 }
 
 pub fn codegen_stylesheet(ss: &Vec<Rule>) -> String {
-    ss.iter().map(|r| r.codegen()).collect()
+    let mut code = String::new();
+    // The at-rule blocks that are currently open, outermost first.
+    let mut open: Vec<&String> = vec![];
+
+    for rule in ss {
+        let shared = open
+            .iter()
+            .zip(&rule.at_rules)
+            .take_while(|(a, b)| **a == *b)
+            .count();
+
+        for _ in shared..open.len() {
+            code.push_str("} ");
+        }
+        open.truncate(shared);
+
+        for at_rule in &rule.at_rules[shared..] {
+            code.push_str(&format!("{} {{", at_rule));
+            open.push(at_rule);
+        }
+
+        code.push_str(&rule.codegen());
+
+        // The declarations of an at-rule like `@font-face` are its block, so a following one needs
+        // a block of its own.
+        if let Selector::None = rule.selector {
+            code.push_str("} ");
+            open.pop();
+        }
+    }
+
+    for _ in open {
+        code.push_str("} ");
+    }
+
+    code
 }
 
 impl Rule {
     fn codegen(&self) -> String {
-        let mut code = format!("{} {{", self.selector.codegen());
+        let mut code = match self.selector {
+            Selector::None => String::new(),
+            _ => format!("{} {{", self.selector.codegen()),
+        };
 
         for prop in &self.properties {
-            code.push_str(&format!("{}:{};", prop.name, prop.value.join(" ")));
+            code.push_str(&format!("{}:{};", prop.name, value_to_css(&prop.value)));
         }
 
-        code.push_str("} ");
+        if !matches!(self.selector, Selector::None) {
+            code.push_str("} ");
+        }
 
         code
     }
 }
 
 impl Selector {
-    fn codegen(&self) -> String {
+    pub(crate) fn codegen(&self) -> String {
         match self {
             Selector::None => unreachable!(),
             Selector::All => "*".to_string(),
@@ -909,9 +1627,13 @@ impl Selector {
             Selector::Class(class) => format!(".{}", class),
             Selector::Tag(tag) => tag.clone(),
             Selector::Pseduo(pseudo) => format!(":{}", pseudo),
+            Selector::Attribute(attr) => format!("[{}]", attr),
+            Selector::Compound(parts) => parts.iter().map(|p| p.codegen()).collect(),
             Selector::Descendant(a, b) => format!("{} {}", a.codegen(), b.codegen()),
             Selector::Child(a, b) => format!("{} > {}", a.codegen(), b.codegen()),
             Selector::NextSibling(a, b) => format!("{} + {}", a.codegen(), b.codegen()),
+            Selector::SubsequentSibling(a, b) => format!("{} ~ {}", a.codegen(), b.codegen()),
+            Selector::Keyframe(keyframe) => keyframe.clone(),
         }
     }
 }
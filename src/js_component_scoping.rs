@@ -1,13 +1,23 @@
-use std::{cell::RefCell, collections::HashMap, error::Error, rc::Rc, sync::atomic::Ordering};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    error::Error,
+    rc::Rc,
+    sync::atomic::Ordering,
+};
 
-use ress::{tokens::Token, Item};
+use ress::tokens::Token;
 
-use crate::{utils::find_and_replace_js_identifiers, Component, JSExpression, Node, ID_COUNTER};
+use crate::{Component, JSExpression, Node, ID_COUNTER};
 
 #[derive(Debug, Clone)]
 pub struct ComponentVariableRenamer {
     prefix: String,
-    identifiers: Rc<RefCell<Vec<String>>>,
+    identifiers: Rc<RefCell<HashSet<String>>>,
+    /// Identifiers declared by the component's `<script context="module">`, which are shared by
+    /// all its instances so get a prefix without an instance id.
+    module_prefix: String,
+    module_identifiers: Rc<RefCell<HashSet<String>>>,
 }
 
 impl ComponentVariableRenamer {
@@ -18,7 +28,9 @@ impl ComponentVariableRenamer {
                 component_name,
                 ID_COUNTER.fetch_add(1, Ordering::SeqCst)
             ),
-            identifiers: Rc::new(RefCell::new(vec![])),
+            identifiers: Rc::new(RefCell::new(HashSet::new())),
+            module_prefix: format!("__{}_module_", component_name),
+            module_identifiers: Rc::new(RefCell::new(HashSet::new())),
         }
     }
 
@@ -27,62 +39,756 @@ impl ComponentVariableRenamer {
     }
 
     pub fn process(&self, expr: &JSExpression) -> JSExpression {
-        let mut expr = expr.clone();
-
         self.identifiers
             .borrow_mut()
-            .extend(get_declared_js_identifiers(&expr).into_iter());
+            .extend(top_level_declarations(expr));
 
-        self.identifiers.borrow_mut().dedup();
+        self.process_no_declared(expr)
+    }
 
-        let expr = self.process_no_declared(&expr);
+    /// Like `process`, but for the code of the component's module script.
+    pub fn process_module(&self, expr: &JSExpression) -> JSExpression {
+        self.module_identifiers
+            .borrow_mut()
+            .extend(top_level_declarations(expr));
 
-        expr
+        self.process_no_declared(expr)
     }
 
     pub fn process_no_declared(&self, expr: &JSExpression) -> JSExpression {
-        let mut expr = expr.clone();
+        let identifiers = self.identifiers.borrow();
+        let module_identifiers = self.module_identifiers.borrow();
+
+        rename_free_identifiers(expr, |ident| {
+            if identifiers.contains(ident) {
+                Some(self.rename(&ident.to_string()))
+            } else if module_identifiers.contains(ident) {
+                // Instance declarations shadow the module ones.
+                Some(format!("{}{}", self.module_prefix, ident))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+/// The names `code` declares at its top level: `let`, `const` and `var` bindings (including
+/// destructured ones), function and class declarations, and `var`s hoisted out of blocks.
+pub fn top_level_declarations(code: &str) -> Vec<String> {
+    let tokens = tokenize(code);
+    let analysis = ScopeAnalyzer::new(&tokens).run();
+
+    let mut names = vec![];
+
+    for name in &analysis.scopes[0].names {
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+
+    names
+}
+
+/// Replaces every identifier in `code` that `rename` gives a new name for, unless it's a property
+/// name, an object key or bound by a function, block or catch clause inside `code`. `$name` is
+/// renamed along with `name`, and shorthand properties are expanded to keep their key.
+pub fn rename_free_identifiers(code: &str, rename: impl Fn(&str) -> Option<String>) -> String {
+    let tokens = tokenize(code);
+    let analysis = ScopeAnalyzer::new(&tokens).run();
+
+    let mut output = String::new();
+    let mut last = 0;
+
+    for occurrence in &analysis.occurrences {
+        let token = &tokens[occurrence.token];
+
+        let (dollar, name) = match token.text.strip_prefix('$') {
+            Some(name) if !name.is_empty() => ("$", name),
+            _ => ("", token.text),
+        };
+
+        let shadowed = analysis.scopes[1..].iter().any(|scope| {
+            scope.start <= occurrence.token
+                && occurrence.token <= scope.end
+                && scope.names.iter().any(|n| n == name)
+        });
+
+        if shadowed {
+            continue;
+        }
+
+        if let Some(renamed) = rename(name) {
+            output.push_str(&code[last..token.start]);
+
+            if occurrence.shorthand {
+                output.push_str(&format!("{}: {}{}", token.text, dollar, renamed));
+            } else {
+                output.push_str(&format!("{}{}", dollar, renamed));
+            }
+
+            last = token.end;
+        }
+    }
+
+    output.push_str(&code[last..]);
+
+    output
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TokenKind {
+    Ident,
+    Keyword,
+    Punct,
+    Other,
+}
+
+#[derive(Debug)]
+struct JsToken<'a> {
+    text: &'a str,
+    kind: TokenKind,
+    start: usize,
+    end: usize,
+    /// Whether there's a line break between this and the previous token, for automatic semicolon
+    /// insertion.
+    newline_before: bool,
+}
+
+fn tokenize(code: &str) -> Vec<JsToken<'_>> {
+    let mut tokens = vec![];
+    let mut last_line = None;
 
-        for ident in self.identifiers.borrow().iter() {
-            let repl = self.rename(ident);
-            expr = find_and_replace_js_identifiers(&expr, ident, &repl);
+    for item in ress::Scanner::new(code).flatten() {
+        if item.token.is_comment() || item.token.is_eof() {
+            continue;
         }
 
-        expr
+        let kind = match item.token {
+            Token::Ident(_) => TokenKind::Ident,
+            Token::Keyword(_) => TokenKind::Keyword,
+            Token::Punct(_) => TokenKind::Punct,
+            _ => TokenKind::Other,
+        };
+
+        tokens.push(JsToken {
+            text: &code[item.span.start..item.span.end],
+            kind,
+            start: item.span.start,
+            end: item.span.end,
+            newline_before: last_line.is_some_and(|line| item.location.start.line > line),
+        });
+
+        last_line = Some(item.location.end.line);
     }
+
+    tokens
 }
 
-fn get_declared_js_identifiers(expr: &JSExpression) -> Vec<String> {
-    let mut identifiers = vec![];
+/// A function, block or `for`/`catch` scope spanning the tokens `start..=end`. Scope 0 is the top
+/// level of the code.
+#[derive(Debug)]
+struct Scope {
+    start: usize,
+    end: usize,
+    function: bool,
+    names: Vec<String>,
+}
+
+/// An identifier that refers to or declares a variable, rather than naming a property.
+#[derive(Debug)]
+struct Occurrence {
+    token: usize,
+    /// `{ name }` in an object literal or pattern.
+    shorthand: bool,
+}
 
-    let mut previous: Option<Token<&str>> = None;
+struct ScopeAnalysis {
+    scopes: Vec<Scope>,
+    occurrences: Vec<Occurrence>,
+}
+
+/// What the innermost bracket being walked through contains.
+#[derive(Debug, Clone, Copy)]
+enum Frame {
+    /// A block or function body, whose `let`, `const`, `class` and `function` declarations go in
+    /// `scope`.
+    Block(usize),
+    /// The head of a `for` or `catch`, whose declarations belong to the block after it.
+    Head(usize),
+    Group,
+    Object {
+        key: bool,
+    },
+    Class {
+        member: bool,
+    },
+    /// A destructuring pattern or parameter list declaring into `scope`.
+    Pattern {
+        scope: usize,
+        object: bool,
+        key: bool,
+        default: bool,
+        params: bool,
+    },
+}
+
+struct ScopeAnalyzer<'t, 'a> {
+    tokens: &'t [JsToken<'a>],
+    matching: Vec<Option<usize>>,
+    scopes: Vec<Scope>,
+    occurrences: Vec<Occurrence>,
+    frames: Vec<Frame>,
+    /// `let`, `const` and `var` statements being walked through, as the frame depth they're at
+    /// and the scope they declare into.
+    declarations: Vec<(usize, usize)>,
+    /// The scope the next identifier or pattern declares into.
+    expect_binding: Option<usize>,
+    /// The scope of a function whose parameter list is the next `(`.
+    pending_params: Option<usize>,
+    /// The scope of the function, `for` or `catch` whose body is the next `{`.
+    pending_block: Option<usize>,
+    /// The frame depth of a `class` whose body is the next `{` at that depth.
+    pending_class: Option<usize>,
+    /// The scopes of arrow functions by the index of their `=>`.
+    arrows: HashMap<usize, usize>,
+}
 
-    for item in ress::Scanner::new(&expr) {
-        if let Ok(item) = item {
-            if is_def(&item, previous) {
-                let ident = item.token.to_string();
-                if !identifiers.contains(&ident) {
-                    identifiers.push(ident);
+impl<'t, 'a> ScopeAnalyzer<'t, 'a> {
+    fn new(tokens: &'t [JsToken<'a>]) -> Self {
+        let mut matching = vec![None; tokens.len()];
+        let mut open = vec![];
+
+        for (i, token) in tokens.iter().enumerate() {
+            match token.text {
+                "(" | "[" | "{" if token.kind == TokenKind::Punct => open.push(i),
+                ")" | "]" | "}" if token.kind == TokenKind::Punct => {
+                    if let Some(o) = open.pop() {
+                        matching[o] = Some(i);
+                        matching[i] = Some(o);
+                    }
                 }
+                _ => {}
             }
-            previous = Some(item.token);
+        }
+
+        ScopeAnalyzer {
+            tokens,
+            matching,
+            scopes: vec![Scope {
+                start: 0,
+                end: tokens.len(),
+                function: true,
+                names: vec![],
+            }],
+            occurrences: vec![],
+            frames: vec![Frame::Block(0)],
+            declarations: vec![],
+            expect_binding: None,
+            pending_params: None,
+            pending_block: None,
+            pending_class: None,
+            arrows: HashMap::new(),
         }
     }
 
-    identifiers
-}
+    fn text(&self, i: usize) -> &'a str {
+        self.tokens.get(i).map_or("", |t| t.text)
+    }
+
+    fn is_punct(&self, i: usize, text: &str) -> bool {
+        self.tokens
+            .get(i)
+            .is_some_and(|t| t.kind == TokenKind::Punct && t.text == text)
+    }
+
+    fn is_ident(&self, i: usize) -> bool {
+        self.tokens
+            .get(i)
+            .is_some_and(|t| t.kind == TokenKind::Ident)
+    }
+
+    fn close_of(&self, i: usize) -> usize {
+        self.matching[i].unwrap_or(self.tokens.len())
+    }
+
+    fn new_scope(&mut self, start: usize, end: usize, function: bool) -> usize {
+        self.scopes.push(Scope {
+            start,
+            end,
+            function,
+            names: vec![],
+        });
+
+        self.scopes.len() - 1
+    }
+
+    fn bind(&mut self, token: usize, scope: usize, shorthand: bool) {
+        let name = self.text(token).to_string();
+        self.scopes[scope].names.push(name);
+        self.occurrences.push(Occurrence { token, shorthand });
+    }
+
+    fn reference(&mut self, token: usize, shorthand: bool) {
+        self.occurrences.push(Occurrence { token, shorthand });
+    }
+
+    fn block_scope(&self) -> usize {
+        self.frames
+            .iter()
+            .rev()
+            .find_map(|frame| match frame {
+                Frame::Block(scope) | Frame::Head(scope) => Some(*scope),
+                _ => None,
+            })
+            .unwrap_or(0)
+    }
+
+    fn function_scope(&self) -> usize {
+        self.frames
+            .iter()
+            .rev()
+            .find_map(|frame| match frame {
+                Frame::Block(scope) if self.scopes[*scope].function => Some(*scope),
+                _ => None,
+            })
+            .unwrap_or(0)
+    }
+
+    fn ends_expression(&self, i: usize) -> bool {
+        let token = &self.tokens[i];
 
-fn is_def(item: &Item<&str>, previous: Option<Token<&str>>) -> bool {
-    if item.token.is_ident() {
-        if let Some(ref prev) = previous {
-            if let Token::Keyword(kw) = prev {
-                let kw = kw.as_str();
-                if kw == "let" || kw == "const" || kw == "var" || kw == "function" {
-                    return true;
+        match token.kind {
+            TokenKind::Ident | TokenKind::Other => true,
+            TokenKind::Keyword => token.text == "this" || token.text == "super",
+            TokenKind::Punct => matches!(token.text, ")" | "]" | "}" | "++" | "--"),
+        }
+    }
+
+    /// Whether automatic semicolon insertion ends a statement right before `i`.
+    fn ends_statement_before(&self, i: usize) -> bool {
+        self.tokens[i].newline_before
+            && self.tokens[i].kind != TokenKind::Punct
+            && self.ends_expression(i - 1)
+    }
+
+    fn starts_statement(&self, i: usize) -> bool {
+        if i == 0 {
+            return true;
+        }
+
+        if self.ends_statement_before(i) {
+            return true;
+        }
+
+        matches!(self.text(i - 1), ";" | "{" | "}")
+    }
+
+    /// Where the expression starting at `start` ends: the first `,` or `;` outside of any
+    /// brackets, the bracket closing the one it's in, or a line break ending the statement.
+    fn expression_end(&self, start: usize) -> usize {
+        let mut depth = 0;
+
+        for i in start..self.tokens.len() {
+            if self.tokens[i].kind != TokenKind::Punct {
+                if depth == 0 && i > start && self.ends_statement_before(i) {
+                    return i;
                 }
+
+                continue;
+            }
+
+            match self.text(i) {
+                "(" | "[" | "{" => depth += 1,
+                ")" | "]" | "}" if depth == 0 => return i,
+                ")" | "]" | "}" => depth -= 1,
+                "," | ";" if depth == 0 => return i,
+                _ => {}
             }
         }
+
+        self.tokens.len()
     }
 
-    false
+    /// The end of the body of a function, `for` or `catch` whose head ends at `close`.
+    fn body_end(&self, close: usize) -> usize {
+        if self.is_punct(close + 1, "{") {
+            self.close_of(close + 1)
+        } else {
+            self.expression_end(close + 1)
+        }
+    }
+
+    /// The scope of a function or method whose parameter list starts at `paren`.
+    fn function_scope_at(&mut self, start: usize, paren: usize) -> usize {
+        let end = self.body_end(self.close_of(paren));
+        self.new_scope(start, end, true)
+    }
+
+    fn run(mut self) -> ScopeAnalysis {
+        let mut i = 0;
+
+        while i < self.tokens.len() {
+            let expect_binding = self.expect_binding.take();
+
+            if let Some(&(depth, _)) = self.declarations.last() {
+                if depth == self.frames.len() && i > 0 && self.ends_statement_before(i) {
+                    self.declarations.pop();
+                }
+            }
+
+            i = match self.tokens[i].kind {
+                TokenKind::Keyword => self.keyword(i),
+                TokenKind::Ident => self.ident(i, expect_binding),
+                TokenKind::Punct => self.punct(i, expect_binding),
+                TokenKind::Other => i + 1,
+            };
+        }
+
+        ScopeAnalysis {
+            scopes: self.scopes,
+            occurrences: self.occurrences,
+        }
+    }
+
+    fn keyword(&mut self, i: usize) -> usize {
+        match self.text(i) {
+            kw @ ("let" | "const" | "var") => {
+                let scope = if kw == "var" {
+                    self.function_scope()
+                } else {
+                    self.block_scope()
+                };
+
+                self.declarations.push((self.frames.len(), scope));
+                self.expect_binding = Some(scope);
+
+                i + 1
+            }
+            "function" => {
+                let declaration = self.starts_statement(i)
+                    || (i > 0 && self.text(i - 1) == "async" && self.starts_statement(i - 1));
+
+                let mut j = i + 1;
+                if self.is_punct(j, "*") {
+                    j += 1;
+                }
+
+                let name = if self.is_ident(j) {
+                    j += 1;
+                    Some(j - 1)
+                } else {
+                    None
+                };
+
+                if !self.is_punct(j, "(") {
+                    return j;
+                }
+
+                // A function expression's name is only visible inside it.
+                let start = if declaration { j } else { i };
+                let scope = self.function_scope_at(start, j);
+
+                if let Some(name) = name {
+                    let target = if declaration {
+                        self.block_scope()
+                    } else {
+                        scope
+                    };
+                    self.bind(name, target, false);
+                }
+
+                self.pending_params = Some(scope);
+
+                j
+            }
+            "class" => {
+                let mut j = i + 1;
+
+                if self.is_ident(j) {
+                    if self.starts_statement(i) {
+                        let scope = self.block_scope();
+                        self.bind(j, scope, false);
+                    }
+                    j += 1;
+                }
+
+                self.pending_class = Some(self.frames.len());
+
+                j
+            }
+            _ => i + 1,
+        }
+    }
+
+    fn ident(&mut self, i: usize, expect_binding: Option<usize>) -> usize {
+        if i > 0 && self.is_punct(i - 1, ".") {
+            return i + 1;
+        }
+
+        if self.is_punct(i + 1, "=>") {
+            let end = self.body_end(i + 1);
+            let scope = self.new_scope(i, end, true);
+            self.bind(i, scope, false);
+            self.arrows.insert(i + 1, scope);
+            return i + 1;
+        }
+
+        if let Some(scope) = expect_binding {
+            self.bind(i, scope, false);
+            return i + 1;
+        }
+
+        let after_spread = i > 0 && self.is_punct(i - 1, "...");
+        let next = self.text(i + 1);
+        let is_modifier = matches!(self.text(i), "get" | "set" | "async")
+            && (self.is_ident(i + 1)
+                || matches!(next, "[" | "*")
+                || self
+                    .tokens
+                    .get(i + 1)
+                    .is_some_and(|t| t.kind == TokenKind::Other || t.kind == TokenKind::Keyword));
+
+        let top = self.frames.len() - 1;
+
+        match self.frames[top] {
+            Frame::Object { key: true } if !after_spread => {
+                if is_modifier {
+                    return i + 1;
+                }
+
+                self.frames[top] = Frame::Object { key: false };
+
+                match next {
+                    ":" => {}
+                    "(" => self.pending_params = Some(self.function_scope_at(i + 1, i + 1)),
+                    _ => self.reference(i, true),
+                }
+            }
+            Frame::Class { member: true } => {
+                if is_modifier {
+                    return i + 1;
+                }
+
+                self.frames[top] = Frame::Class { member: false };
+
+                if next == "(" {
+                    self.pending_params = Some(self.function_scope_at(i + 1, i + 1));
+                }
+            }
+            Frame::Pattern {
+                scope,
+                object: true,
+                key: true,
+                default,
+                params,
+            } => {
+                self.frames[top] = Frame::Pattern {
+                    scope,
+                    object: true,
+                    key: false,
+                    default,
+                    params,
+                };
+
+                if after_spread {
+                    self.bind(i, scope, false);
+                } else if next != ":" {
+                    self.bind(i, scope, true);
+                }
+            }
+            Frame::Pattern {
+                scope,
+                object: false,
+                default: false,
+                ..
+            } => self.bind(i, scope, false),
+            _ => self.reference(i, false),
+        }
+
+        i + 1
+    }
+
+    fn punct(&mut self, i: usize, expect_binding: Option<usize>) -> usize {
+        let top = self.frames.len() - 1;
+
+        // Elements of array patterns and parameter lists can be patterns themselves.
+        let pattern_scope = expect_binding.or(match self.frames[top] {
+            Frame::Pattern {
+                scope,
+                object: false,
+                default: false,
+                ..
+            } => Some(scope),
+            _ => None,
+        });
+
+        match self.text(i) {
+            "(" => {
+                let close = self.close_of(i);
+
+                let frame = if let Some(scope) = self.pending_params.take() {
+                    Frame::Pattern {
+                        scope,
+                        object: false,
+                        key: false,
+                        default: false,
+                        params: true,
+                    }
+                } else if self.is_punct(close + 1, "=>") {
+                    let end = self.body_end(close + 1);
+                    let scope = self.new_scope(i, end, true);
+                    self.arrows.insert(close + 1, scope);
+
+                    Frame::Pattern {
+                        scope,
+                        object: false,
+                        key: false,
+                        default: false,
+                        params: false,
+                    }
+                } else if i > 0 && self.text(i - 1) == "catch" {
+                    let end = self.body_end(close);
+
+                    Frame::Pattern {
+                        scope: self.new_scope(i, end, false),
+                        object: false,
+                        key: false,
+                        default: false,
+                        params: true,
+                    }
+                } else if i > 0 && matches!(self.text(i - 1), "for" | "await") {
+                    let end = self.body_end(close);
+                    Frame::Head(self.new_scope(i, end, false))
+                } else {
+                    Frame::Group
+                };
+
+                self.frames.push(frame);
+            }
+            "[" => {
+                let frame = if let Some(scope) = pattern_scope {
+                    Frame::Pattern {
+                        scope,
+                        object: false,
+                        key: false,
+                        default: false,
+                        params: false,
+                    }
+                } else {
+                    Frame::Group
+                };
+
+                self.frames.push(frame);
+            }
+            "{" => {
+                let frame = if let Some(scope) = self.pending_block.take() {
+                    Frame::Block(scope)
+                } else if let Some(scope) = pattern_scope {
+                    Frame::Pattern {
+                        scope,
+                        object: true,
+                        key: true,
+                        default: false,
+                        params: false,
+                    }
+                } else if self.pending_class == Some(self.frames.len()) {
+                    self.pending_class = None;
+                    Frame::Class { member: true }
+                } else if i > 0
+                    && (matches!(
+                        self.text(i - 1),
+                        ")" | "=>" | "else" | "try" | "finally" | "do"
+                    ) || self.starts_statement(i))
+                {
+                    // An expression on its own is more likely to be an object than a block.
+                    let end = self.close_of(i);
+                    Frame::Block(self.new_scope(i, end, false))
+                } else {
+                    Frame::Object { key: true }
+                };
+
+                self.frames.push(frame);
+            }
+            ")" | "]" | "}" => {
+                if self.frames.len() > 1 {
+                    let frame = self.frames.pop().unwrap();
+
+                    match frame {
+                        Frame::Pattern {
+                            scope,
+                            params: true,
+                            ..
+                        }
+                        | Frame::Head(scope)
+                            if self.is_punct(i + 1, "{") =>
+                        {
+                            self.pending_block = Some(scope)
+                        }
+                        _ => {}
+                    }
+                }
+
+                let depth = self.frames.len();
+                self.declarations.retain(|&(d, _)| d <= depth);
+
+                let top = self.frames.len() - 1;
+                if let Frame::Class { .. } = self.frames[top] {
+                    self.frames[top] = Frame::Class { member: true };
+                }
+            }
+            "," => {
+                match &mut self.frames[top] {
+                    Frame::Object { key } => *key = true,
+                    Frame::Pattern { key, default, .. } => {
+                        *key = true;
+                        *default = false;
+                    }
+                    _ => {}
+                }
+
+                if let Some(&(depth, scope)) = self.declarations.last() {
+                    if depth == self.frames.len() {
+                        self.expect_binding = Some(scope);
+                    }
+                }
+            }
+            ":" => {
+                if let Frame::Pattern {
+                    scope,
+                    object: true,
+                    default: false,
+                    ..
+                } = self.frames[top]
+                {
+                    self.expect_binding = Some(scope);
+                }
+            }
+            "=" => {
+                if let Frame::Pattern { default, .. } = &mut self.frames[top] {
+                    *default = true;
+                }
+            }
+            "=>" => {
+                if let Some(&scope) = self.arrows.get(&i) {
+                    if self.is_punct(i + 1, "{") {
+                        self.pending_block = Some(scope);
+                    }
+                }
+            }
+            ";" => {
+                if let Some(&(depth, _)) = self.declarations.last() {
+                    if depth == self.frames.len() {
+                        self.declarations.pop();
+                    }
+                }
+
+                if let Frame::Class { member } = &mut self.frames[top] {
+                    *member = true;
+                }
+            }
+            _ => {}
+        }
+
+        i + 1
+    }
 }
@@ -0,0 +1,517 @@
+use std::collections::HashMap;
+
+use ress::tokens::{Punct, Token};
+
+use crate::{utils::children_of, Component, Node};
+
+/// A plain `.js` file in the project. Modules are bundled once, each into an object of its exports
+/// that importers take the names they use from.
+#[derive(Debug, Clone)]
+pub struct JsModule {
+    /// The path relative to the project root, which imports are resolved to.
+    path: String,
+    /// The code with its imports replaced by bindings and `export` keywords removed.
+    code: String,
+    imports: Vec<Import>,
+    /// Exported names along with the local names they refer to.
+    exports: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone)]
+struct Import {
+    /// The resolved path of the imported module.
+    module: String,
+    /// Imported names along with the local names they're bound to.
+    names: Vec<(String, String)>,
+}
+
+impl JsModule {
+    pub fn new(path: &str, code: &str) -> Result<Self, String> {
+        if path.ends_with(".ts") {
+            return Err(format!(
+                "{path}: TypeScript modules aren't supported yet as their types would end up in the bundle, use a .js module instead."
+            ));
+        }
+
+        let syntax =
+            parse_module_syntax(code, parent_dir(path)).map_err(|e| format!("{path}: {e}"))?;
+
+        for (i, (exported, _)) in syntax.exports.iter().enumerate() {
+            if syntax.exports[..i]
+                .iter()
+                .any(|(other, _)| other == exported)
+            {
+                return Err(format!("{path}: '{exported}' is exported more than once."));
+            }
+        }
+
+        Ok(JsModule {
+            path: path.to_string(),
+            code: syntax.code,
+            imports: syntax.imports,
+            exports: syntax.exports,
+        })
+    }
+}
+
+/// Whether a file in the project is a module rather than a component. TypeScript modules are
+/// recognised only so they can be rejected with a proper error.
+pub fn is_module_path(path: &str) -> bool {
+    path.ends_with(".js") || path.ends_with(".ts")
+}
+
+/// The global holding the exports of the module at `path`, which may leave out the extension.
+fn namespace(path: &str) -> String {
+    let path = path.strip_suffix(".js").unwrap_or(path);
+
+    let name = path
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() => c.to_string(),
+            '/' => "__".to_string(),
+            _ => "_".to_string(),
+        })
+        .collect::<String>();
+
+    format!("__module_{name}")
+}
+
+/// Bundles the modules in dependency order. Each module runs in a scope of its own and is
+/// declared globally as an object of its exports, named by `namespace`.
+pub fn bundle_modules(modules: &[JsModule]) -> Result<String, String> {
+    let mut namespaces: HashMap<String, &str> = HashMap::new();
+
+    for module in modules {
+        if let Some(other) = namespaces.insert(namespace(&module.path), &module.path) {
+            return Err(format!(
+                "The modules {other} and {} can't be told apart once bundled, rename one of them.",
+                module.path
+            ));
+        }
+
+        for import in &module.imports {
+            check_import(import, modules, &module.path)?;
+        }
+    }
+
+    let mut order = vec![];
+
+    for module in modules {
+        visit(module, modules, &mut vec![], &mut order)?;
+    }
+
+    Ok(order
+        .iter()
+        .filter(|module| !module.exports.is_empty() || !module.code.trim().is_empty())
+        .map(|module| {
+            let fields = module
+                .exports
+                .iter()
+                .map(|(exported, local)| match exported == local {
+                    true => exported.clone(),
+                    false => format!("{exported}: {local}"),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            format!(
+                "// {path}\nconst {namespace} = (() => {{\n{code}\nreturn {{ {fields} }};\n}})();\n",
+                path = module.path,
+                namespace = namespace(&module.path),
+                code = module.code
+            )
+        })
+        .collect())
+}
+
+/// Adds `module` to `order` after the modules it imports.
+fn visit<'a>(
+    module: &'a JsModule,
+    modules: &'a [JsModule],
+    stack: &mut Vec<&'a str>,
+    order: &mut Vec<&'a JsModule>,
+) -> Result<(), String> {
+    if order.iter().any(|m| m.path == module.path) {
+        return Ok(());
+    }
+
+    if let Some(start) = stack.iter().position(|p| *p == module.path) {
+        let mut cycle = stack[start..].to_vec();
+        cycle.push(&module.path);
+        return Err(format!("Circular import: {}", cycle.join(" -> ")));
+    }
+
+    stack.push(&module.path);
+
+    for import in &module.imports {
+        let dependency = find_module(modules, &import.module).unwrap();
+        visit(dependency, modules, stack, order)?;
+    }
+
+    stack.pop();
+    order.push(module);
+
+    Ok(())
+}
+
+/// Finds the module at `path`, which may leave out the extension.
+fn find_module<'a>(modules: &'a [JsModule], path: &str) -> Option<&'a JsModule> {
+    modules.iter().find(|m| {
+        m.path == path || m.path.strip_suffix(".js").or(m.path.strip_suffix(".ts")) == Some(path)
+    })
+}
+
+fn check_import(import: &Import, modules: &[JsModule], importer: &str) -> Result<(), String> {
+    let module = find_module(modules, &import.module).ok_or_else(|| {
+        format!(
+            "Cannot find module {} imported by {importer}",
+            import.module
+        )
+    })?;
+
+    for (name, _) in &import.names {
+        if !module.exports.iter().any(|(exported, _)| exported == name) {
+            return Err(format!(
+                "{} has no export named '{name}', imported by {importer}",
+                module.path
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the imports from the scripts of a component, checking them against the modules. `dir`
+/// is the directory of the component relative to the project root.
+pub fn resolve_component_imports(
+    component: &mut Component,
+    dir: &str,
+    modules: &[JsModule],
+) -> Result<(), String> {
+    for child in component.children.iter_mut() {
+        resolve_node_imports(child, &component.name, dir, modules)?;
+    }
+
+    Ok(())
+}
+
+fn resolve_node_imports(
+    node: &mut Node,
+    component: &str,
+    dir: &str,
+    modules: &[JsModule],
+) -> Result<(), String> {
+    if let Node::ScriptTag(script) = node {
+        let syntax =
+            parse_module_syntax(&script.code, dir).map_err(|e| format!("{component}: {e}"))?;
+
+        if !syntax.exports.is_empty() {
+            return Err(format!(
+                "{component}: Components can't export values, move them to a module instead."
+            ));
+        }
+
+        for import in &syntax.imports {
+            check_import(import, modules, component)?;
+        }
+
+        script.code = syntax.code;
+    }
+
+    for children in children_of(node) {
+        for child in children {
+            resolve_node_imports(child, component, dir, modules)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn parent_dir(path: &str) -> &str {
+    path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("")
+}
+
+/// Resolves a relative import specifier against the directory of the importing file.
+fn resolve_specifier(dir: &str, specifier: &str) -> Result<String, String> {
+    if !specifier.starts_with("./") && !specifier.starts_with("../") {
+        return Err(format!(
+            "Cannot import \"{specifier}\", only modules in the project can be imported using a relative path like \"./{specifier}\""
+        ));
+    }
+
+    let mut parts: Vec<&str> = dir.split('/').filter(|p| !p.is_empty()).collect();
+
+    for part in specifier.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                if parts.pop().is_none() {
+                    return Err(format!(
+                        "Cannot import \"{specifier}\" from outside the project"
+                    ));
+                }
+            }
+            part => parts.push(part),
+        }
+    }
+
+    Ok(parts.join("/"))
+}
+
+struct ModuleSyntax {
+    code: String,
+    imports: Vec<Import>,
+    exports: Vec<(String, String)>,
+}
+
+/// Finds the top level import and export statements of a script in `dir`. Imports are replaced by
+/// bindings taking the names from the module's namespace, exports have their `export` removed.
+fn parse_module_syntax(code: &str, dir: &str) -> Result<ModuleSyntax, String> {
+    let mut tokens = vec![];
+
+    for item in ress::Scanner::new(code) {
+        let item = item.map_err(|e| format!("Unable to tokenize script: {e}"))?;
+
+        if item.token.is_eof() {
+            break;
+        }
+
+        if !item.token.is_comment() {
+            tokens.push(item);
+        }
+    }
+
+    let text = |i: usize| &code[tokens[i].span.start..tokens[i].span.end];
+
+    let mut replacements: Vec<(usize, usize, String)> = vec![];
+    let mut imports = vec![];
+    let mut exports = vec![];
+
+    let mut depth = 0;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let token = &tokens[i].token;
+        let next = tokens.get(i + 1).map(|t| &t.token);
+
+        match token {
+            Token::Punct(Punct::OpenBrace | Punct::OpenParen | Punct::OpenBracket) => depth += 1,
+            Token::Punct(Punct::CloseBrace | Punct::CloseParen | Punct::CloseBracket) => depth -= 1,
+            // `import(...)` and `import.meta` aren't import statements.
+            Token::Keyword(kw)
+                if depth == 0
+                    && kw.as_str() == "import"
+                    && !next.is_some_and(|t| {
+                        t.matches_punct(Punct::OpenParen) || t.matches_punct(Punct::Period)
+                    }) =>
+            {
+                let start = tokens[i].span.start;
+                i += 1;
+
+                let mut names = vec![];
+
+                if tokens
+                    .get(i)
+                    .is_some_and(|t| t.token.matches_punct(Punct::OpenBrace))
+                {
+                    (names, i) = parse_name_list(&tokens, i, text)?;
+
+                    if !tokens
+                        .get(i)
+                        .is_some_and(|t| t.token.matches_ident_str("from"))
+                    {
+                        return Err("Expected 'from' after the imported names".to_string());
+                    }
+                    i += 1;
+                } else if !tokens.get(i).is_some_and(|t| t.token.is_string()) {
+                    return Err(
+                        "Only named imports are supported, like import { a, b as c } from \"./module.js\""
+                            .to_string(),
+                    );
+                }
+
+                if !tokens.get(i).is_some_and(|t| t.token.is_string()) {
+                    return Err("Expected the path of the imported module".to_string());
+                }
+
+                let specifier = text(i)[1..text(i).len() - 1].to_string();
+                i += 1;
+
+                if tokens
+                    .get(i)
+                    .is_some_and(|t| t.token.matches_punct(Punct::SemiColon))
+                {
+                    i += 1;
+                }
+
+                let end = tokens[i - 1].span.end;
+
+                let module = resolve_specifier(dir, &specifier)?;
+
+                let bindings = names
+                    .iter()
+                    .map(|(imported, local)| match imported == local {
+                        true => imported.clone(),
+                        false => format!("{imported}: {local}"),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                // Imports that only run a module don't bind anything.
+                if !names.is_empty() {
+                    replacements.push((
+                        start,
+                        end,
+                        format!("const {{ {bindings} }} = {};", namespace(&module)),
+                    ));
+                } else {
+                    replacements.push((start, end, "".to_string()));
+                }
+
+                imports.push(Import { module, names });
+
+                continue;
+            }
+            Token::Keyword(kw) if depth == 0 && kw.as_str() == "export" => {
+                let start = tokens[i].span.start;
+                i += 1;
+
+                let Some(next) = tokens.get(i) else {
+                    return Err("Expected a declaration after export".to_string());
+                };
+
+                if next.token.matches_punct(Punct::OpenBrace) {
+                    let names;
+                    (names, i) = parse_name_list(&tokens, i, text)?;
+
+                    if tokens
+                        .get(i)
+                        .is_some_and(|t| t.token.matches_ident_str("from"))
+                    {
+                        return Err("Re-exporting from another module is not supported".to_string());
+                    }
+
+                    if tokens
+                        .get(i)
+                        .is_some_and(|t| t.token.matches_punct(Punct::SemiColon))
+                    {
+                        i += 1;
+                    }
+
+                    let end = tokens[i - 1].span.end;
+                    replacements.push((start, end, "".to_string()));
+
+                    exports.extend(names.into_iter().map(|(local, exported)| (exported, local)));
+
+                    continue;
+                }
+
+                let mut j = i;
+
+                if tokens[j].token.matches_ident_str("async") {
+                    j += 1;
+                }
+
+                let is_declaration = tokens.get(j).is_some_and(|t| match &t.token {
+                    Token::Keyword(kw) => {
+                        ["const", "let", "var", "function", "class"].contains(&kw.as_str())
+                    }
+                    _ => false,
+                });
+
+                if !is_declaration {
+                    return Err(format!(
+                        "Unsupported export '{}', only named exports of declarations and export {{ ... }} are supported",
+                        text(i)
+                    ));
+                }
+
+                j += 1;
+
+                if tokens
+                    .get(j)
+                    .is_some_and(|t| t.token.matches_punct(Punct::Asterisk))
+                {
+                    j += 1;
+                }
+
+                if !tokens.get(j).is_some_and(|t| t.token.is_ident()) {
+                    return Err("Expected the name of the exported declaration".to_string());
+                }
+
+                let name = text(j).to_string();
+                exports.push((name.clone(), name));
+
+                replacements.push((start, tokens[i].span.start, "".to_string()));
+
+                continue;
+            }
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    let mut code = code.to_string();
+
+    for (start, end, replacement) in replacements.into_iter().rev() {
+        code.replace_range(start..end, &replacement);
+    }
+
+    Ok(ModuleSyntax {
+        code,
+        imports,
+        exports,
+    })
+}
+
+/// Parses `{ a, b as c }` starting at the opening brace. Returns the names along with their
+/// aliases and the index after the closing brace.
+fn parse_name_list<'a>(
+    tokens: &[ress::Item<&str>],
+    mut i: usize,
+    text: impl Fn(usize) -> &'a str,
+) -> Result<(Vec<(String, String)>, usize), String> {
+    let mut names = vec![];
+
+    i += 1;
+
+    loop {
+        let Some(token) = tokens.get(i).map(|t| &t.token) else {
+            return Err("Expected }".to_string());
+        };
+
+        if token.matches_punct(Punct::CloseBrace) {
+            return Ok((names, i + 1));
+        }
+
+        if !token.is_ident() && !matches!(token, Token::Keyword(_)) {
+            return Err(format!("Unexpected '{}' in list of names", text(i)));
+        }
+
+        let name = text(i).to_string();
+        let mut alias = name.clone();
+        i += 1;
+
+        if tokens
+            .get(i)
+            .is_some_and(|t| t.token.matches_ident_str("as"))
+        {
+            if !tokens.get(i + 1).is_some_and(|t| t.token.is_ident()) {
+                return Err(format!("Expected a name after '{name} as'"));
+            }
+
+            alias = text(i + 1).to_string();
+            i += 2;
+        }
+
+        names.push((name, alias));
+
+        if tokens
+            .get(i)
+            .is_some_and(|t| t.token.matches_punct(Punct::Comma))
+        {
+            i += 1;
+        }
+    }
+}
@@ -2,16 +2,22 @@ use std::{collections::HashMap, sync::atomic::AtomicUsize};
 
 use css::StyleSheet;
 
+pub mod character_references;
 pub mod codegen;
 pub mod compile;
+pub mod component_events;
 pub mod config;
 pub mod css;
 pub mod css_component_scoping;
+pub mod css_lint;
 pub mod job;
 pub mod js_codegen;
 pub mod js_component_scoping;
+pub mod js_modules;
+pub mod minify;
 pub mod parse;
 pub mod rs_codegen;
+pub mod source_map;
 pub mod utils;
 
 pub static ID_COUNTER: AtomicUsize = AtomicUsize::new(0);
@@ -40,16 +46,38 @@ pub fn wasm_compile(files: Vec<File>) -> String {
 }
 
 pub fn wasm_compile_(files: Vec<File>) -> Result<String, String> {
+    compile_files(files, false)
+}
+
+/// Compiles the files of a web project, with components and modules told apart by their names.
+pub fn compile_files(files: Vec<File>, release: bool) -> Result<String, String> {
     let mut components_map = HashMap::new();
 
     let mut stylesheet = vec![];
 
+    let (module_files, files): (Vec<_>, Vec<_>) = files
+        .into_iter()
+        .partition(|file| js_modules::is_module_path(&file.name));
+
+    let modules = module_files
+        .iter()
+        .map(|File { name, contents }| js_modules::JsModule::new(name, contents))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let bundle = js_modules::bundle_modules(&modules)?;
+
+    let mut sources = HashMap::new();
+
     for File { name, contents } in files {
-        let component = parse::parse_full(&contents, &name, Dialect::JsLilac)
+        let mut component = parse::parse_full(&contents, &name, Dialect::JsLilac)
             .map_err(|err| err.format(name.as_str(), &contents))?;
 
+        js_modules::resolve_component_imports(&mut component, "", &modules)?;
+
         let (styles, component) = job::collect_css(component);
 
+        let (styles, _warnings) = css_lint::lint_component_css(&component, styles, false);
+
         let (styles, component) = css_component_scoping::scope_css_to_component(component, styles);
 
         stylesheet.extend(styles);
@@ -61,6 +89,13 @@ pub fn wasm_compile_(files: Vec<File>) -> Result<String, String> {
             ));
         }
 
+        sources.insert(
+            component.name.clone(),
+            source_map::SourceFile {
+                path: format!("{}.lilac", name),
+                contents,
+            },
+        );
         components_map.insert(component.name.clone(), component);
     }
 
@@ -78,7 +113,7 @@ pub fn wasm_compile_(files: Vec<File>) -> Result<String, String> {
 
     compile::fill_holes(&mut root_node, &components_map).map_err(|e| e.to_string())?;
 
-    root_node.codegen(job::Target::Web, stylesheet)
+    root_node.codegen(job::Target::Web, stylesheet, &bundle, &sources, release)
 }
 
 pub type JSExpression = String;
@@ -97,28 +132,92 @@ pub enum Node {
     ScriptTag(ScriptTag),
     StyleTag(StyleSheet),
     Text(String),
-    ReactiveText(String),
+    ReactiveText(JSExpression, Span),
     ConditionalElements {
         condition: JSExpression,
         children: Vec<Node>,
     },
     Loop {
         iterator_variable: String,
+        index_variable: Option<String>,
         iteratable: JSExpression,
         reactive_list: bool,
         children: Vec<Node>,
+        /// Shown instead of the items while the list is empty.
+        empty: Vec<Node>,
+    },
+    Key {
+        key: JSExpression,
+        children: Vec<Node>,
+    },
+    Await {
+        promise: JSExpression,
+        pending: Vec<Node>,
+        then: Option<AwaitBranch>,
+        catch: Option<AwaitBranch>,
+    },
+    /// A `{@const name = value}` tag at the top of a block body. The value is computed once per
+    /// instance of the block.
+    Const {
+        name: String,
+        value: JSExpression,
+    },
+    /// An `<ErrorBoundary fallback={...}>`. When creating or updating its children throws, they are
+    /// replaced with the fallback, a function of the error or anything to show as is.
+    ErrorBoundary {
+        position: usize,
+        fallback: Option<JSExpression>,
+        children: Vec<Node>,
     },
 }
 
+/// The `{:then value}` or `{:catch error}` part of an await block.
+#[derive(Debug, Clone)]
+pub struct AwaitBranch {
+    pattern: Option<String>,
+    children: Vec<Node>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Element {
     name: String,
     id: Option<Id>,
+    /// The ids of the component's stylesheet a reactive id can have, with the class scoping gives
+    /// an element while it has each.
+    id_classes: Vec<(String, String)>,
     classes: Option<ClassList>,
+    class_directives: Vec<Directive>,
+    style_directives: Vec<Directive>,
+    transitions: Vec<Transition>,
     attributes: Vec<Attribute>,
     children: Vec<Node>,
 }
 
+/// A `class:name={condition}` or `style:property={value}` directive.
+#[derive(Debug, Clone)]
+pub struct Directive {
+    name: String,
+    value: JSExpression,
+}
+
+/// A `transition:name`, `in:name` or `out:name` directive. `name` is one of the built-in
+/// transitions or a function in scope, `params` is passed to it.
+#[derive(Debug, Clone)]
+pub struct Transition {
+    kind: TransitionKind,
+    name: String,
+    params: Option<JSExpression>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransitionKind {
+    /// Played when the element is added by a block.
+    In,
+    /// Played before the element is removed by a block, which waits for it to finish.
+    Out,
+    Both,
+}
+
 #[derive(Debug, Clone)]
 pub enum Id {
     Static(String),
@@ -153,26 +252,43 @@ impl ClassList {
 pub struct ScriptTag {
     attributes: Vec<Attribute>,
     code: JSExpression,
+    span: Span,
+}
+
+impl ScriptTag {
+    /// A `<script context="module">` runs once per component rather than once per instance.
+    pub fn is_module_context(&self) -> bool {
+        self.attributes.iter().any(|a| match a {
+            Attribute::Static(StaticAttribute { name, value }) => {
+                name == "context" && value.as_deref() == Some("module")
+            }
+            _ => false,
+        })
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Attribute {
     Static(StaticAttribute),
     Reactive(ReactiveAttribute),
+    Spread(SpreadAttribute),
 }
 
 impl Attribute {
+    /// Spreads count as reactive since their keys are only known at runtime.
     pub fn is_reactive(&self) -> bool {
         match self {
-            Attribute::Reactive(_) => true,
+            Attribute::Reactive(_) | Attribute::Spread(_) => true,
             _ => false,
         }
     }
 
-    pub fn name<'a>(&'a self) -> &'a String {
+    /// Spreads don't have a name, so this is empty for them.
+    pub fn name(&self) -> &str {
         match self {
             Attribute::Reactive(ra) => &ra.name,
             Attribute::Static(sa) => &sa.name,
+            Attribute::Spread(_) => "",
         }
     }
 }
@@ -187,6 +303,22 @@ pub struct StaticAttribute {
 pub struct ReactiveAttribute {
     name: String,
     value: JSExpression,
+    span: Span,
+}
+
+/// Where a piece of code starts in the file of the component it was written in, which source maps
+/// point back to. Code made up by the compiler has no component.
+#[derive(Debug, Clone, Default)]
+pub struct Span {
+    component: String,
+    /// A byte offset into the file.
+    start: usize,
+}
+
+/// `{...value}`, which sets every key of an object as an attribute or prop.
+#[derive(Debug, Clone)]
+pub struct SpreadAttribute {
+    value: JSExpression,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -204,4 +336,6 @@ pub struct Component {
     children: Vec<Node>,
     /// If the component instance is a child of the same component
     recursive: bool,
+    /// The contents of the component's file, for errors found after parsing.
+    source: String,
 }
@@ -25,6 +25,10 @@ enum Command {
         /// Path to the project
         #[arg(default_value_t = String::from("."))]
         path: String,
+
+        /// Build for release, stripping anything only needed during development.
+        #[arg(long)]
+        release: bool,
     }
 }
 
@@ -33,10 +37,11 @@ fn main() {
 
     if let Some(command) = args.command {
         match command {
-            Command::Build { path } => {
+            Command::Build { path, release } => {
                 let job = lilac::job::Job {
                     path: PathBuf::from(path),
                     output: PathBuf::from(args.output.clone()),
+                    release,
                 };
                 
                 job.run();
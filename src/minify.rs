@@ -0,0 +1,337 @@
+use std::collections::{HashMap, HashSet};
+
+use ress::tokens::{Punct, Token};
+
+/// Minifies a generated HTML document for release builds. Scripts are minified with a rename
+/// map shared between them since they all run in the same global scope. Globals in `keep`, like
+/// the ones the prelude declares, keep their names so code outside the document can still use them.
+pub fn minify_html(html: &str, keep: &[String]) -> String {
+    let mut renamer = IdentifierShortener {
+        keep: keep.to_vec(),
+        taken: script_identifiers(html),
+        ..Default::default()
+    };
+
+    let mut output = String::with_capacity(html.len());
+    let mut pos = 0;
+
+    while pos < html.len() {
+        let rest = &html[pos..];
+
+        let raw_tag = ["script", "style", "pre", "textarea"]
+            .into_iter()
+            .find(|tag| {
+                rest.starts_with(&format!("<{}", tag))
+                    && rest[tag.len() + 1..].starts_with(|c: char| c == '>' || c.is_whitespace())
+            });
+
+        if let Some(tag) = raw_tag {
+            let open_end = match rest.find('>') {
+                Some(i) => i + 1,
+                None => break,
+            };
+            let close = rest[open_end..]
+                .find(&format!("</{}>", tag))
+                .map(|i| i + open_end)
+                .unwrap_or(rest.len());
+
+            let inner = &rest[open_end..close];
+
+            output.push_str(&rest[..open_end]);
+            output.push_str(&match tag {
+                "script" => minify_js(inner, &mut renamer),
+                "style" => minify_css(inner),
+                _ => inner.to_string(),
+            });
+
+            pos += close;
+            continue;
+        }
+
+        let c = rest.chars().next().unwrap();
+
+        if c.is_whitespace() {
+            let end = rest
+                .find(|c: char| !c.is_whitespace())
+                .unwrap_or(rest.len());
+            output.push(' ');
+            pos += end;
+        } else {
+            output.push(c);
+            pos += c.len_utf8();
+        }
+    }
+
+    output.trim().to_string()
+}
+
+/// Every identifier used by the document's scripts, which short names must not collide with.
+fn script_identifiers(html: &str) -> HashSet<String> {
+    let mut identifiers = HashSet::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find("<script") {
+        rest = &rest[start + "<script".len()..];
+
+        if !rest.starts_with(|c: char| c == '>' || c.is_whitespace()) {
+            continue;
+        }
+
+        let Some(open_end) = rest.find('>') else {
+            break;
+        };
+        let close = rest.find("</script>").unwrap_or(rest.len()).max(open_end);
+
+        for item in ress::Scanner::new(&rest[open_end + 1..close]) {
+            match item {
+                Ok(item) => {
+                    if let Token::Ident(ident) = item.token {
+                        identifiers.insert(ident.as_ref().to_string());
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+
+        rest = &rest[close..];
+    }
+
+    identifiers
+}
+
+/// Removes unnecessary whitespace from a stylesheet.
+pub fn minify_css(css: &str) -> String {
+    let mut output = String::with_capacity(css.len());
+    let mut quote = None;
+    let mut pending_space = false;
+
+    for c in css.chars() {
+        if let Some(q) = quote {
+            output.push(c);
+            if c == q {
+                quote = None;
+            }
+            continue;
+        }
+
+        if c.is_whitespace() {
+            pending_space = true;
+            continue;
+        }
+
+        let is_separator = |c: char| "{};,>".contains(c);
+
+        if pending_space
+            && !output.is_empty()
+            && !is_separator(c)
+            && !output.ends_with(is_separator)
+        {
+            output.push(' ');
+        }
+        pending_space = false;
+
+        if c == '}' && output.ends_with(';') {
+            output.pop();
+        }
+
+        if c == '"' || c == '\'' {
+            quote = Some(c);
+        }
+
+        output.push(c);
+    }
+
+    output
+}
+
+/// Gives every generated (double underscore prefixed) identifier a short name. Names that also end
+/// in double underscores, like `__proto__`, belong to the platform and are left alone. Short names
+/// already used in the document, like the loop counter `__i`, are skipped.
+#[derive(Default)]
+struct IdentifierShortener {
+    names: HashMap<String, String>,
+    keep: Vec<String>,
+    taken: HashSet<String>,
+    next: usize,
+}
+
+impl IdentifierShortener {
+    fn shorten(&mut self, ident: &str) -> String {
+        if !ident.starts_with("__")
+            || ident.ends_with("__")
+            || ident.len() <= 3
+            || self.keep.iter().any(|k| k == ident)
+        {
+            return ident.to_string();
+        }
+
+        if let Some(name) = self.names.get(ident) {
+            return name.clone();
+        }
+
+        let name = loop {
+            let name = format!("__{}", short_name(self.next));
+            self.next += 1;
+
+            if !self.taken.contains(&name) {
+                break name;
+            }
+        };
+
+        self.names.insert(ident.to_string(), name.clone());
+        name
+    }
+}
+
+fn short_name(mut n: usize) -> String {
+    const CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+    let mut name = String::new();
+
+    loop {
+        name.push(CHARS[n % CHARS.len()] as char);
+        n /= CHARS.len();
+        if n == 0 {
+            break;
+        }
+    }
+
+    name
+}
+
+/// Strips comments, whitespace and `assert` calls from a script. Line breaks are kept wherever
+/// there was one so automatic semicolon insertion behaves the same. If the script can't be
+/// tokenized it's returned unchanged.
+fn minify_js(js: &str, renamer: &mut IdentifierShortener) -> String {
+    let mut items = vec![];
+
+    for item in ress::Scanner::new(js) {
+        match item {
+            Ok(item) => items.push(item),
+            Err(_) => return js.to_string(),
+        }
+    }
+
+    let mut output = String::with_capacity(js.len());
+
+    let mut last_end = 0;
+    let mut previous: Option<&Token<&str>> = None;
+    let mut i = 0;
+
+    while i < items.len() {
+        let item = &items[i];
+
+        if item.token.is_eof() {
+            break;
+        }
+
+        let gap = &js[last_end..item.span.start];
+        last_end = item.span.end;
+
+        if let Token::Comment(_) = item.token {
+            if js[item.span.start..item.span.end].contains('\n') && !output.ends_with('\n') {
+                output.push('\n');
+            }
+            i += 1;
+            continue;
+        }
+
+        if is_assert_call(&items, i, previous, gap.contains('\n')) {
+            i = skip_call(&items, i);
+            last_end = items[i - 1].span.end;
+            continue;
+        }
+
+        let text = match &item.token {
+            Token::Ident(ident) => renamer.shorten(ident.as_ref()),
+            _ => js[item.span.start..item.span.end].to_string(),
+        };
+
+        if gap.contains('\n') {
+            if !output.is_empty() && !output.ends_with('\n') {
+                output.push('\n');
+            }
+        } else if !gap.is_empty() && needs_space(&output, &text) {
+            output.push(' ');
+        }
+
+        output.push_str(&text);
+        previous = Some(&item.token);
+        i += 1;
+    }
+
+    output
+}
+
+fn needs_space(before: &str, after: &str) -> bool {
+    let (a, b) = match (before.chars().last(), after.chars().next()) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return false,
+    };
+
+    let word = |c: char| c.is_alphanumeric() || c == '_' || c == '$' || !c.is_ascii();
+
+    (word(a) && word(b)) || (a == b && (a == '+' || a == '-')) || a == '/' || b == '/'
+}
+
+/// An `assert(...)` call in statement position, which includes the start of a line after one
+/// ending in an expression, where automatic semicolon insertion ends the statement before it.
+fn is_assert_call(
+    items: &[ress::Item<&str>],
+    i: usize,
+    previous: Option<&Token<&str>>,
+    after_line_break: bool,
+) -> bool {
+    let is_assert = match &items[i].token {
+        Token::Ident(ident) => ident.as_ref() == "assert",
+        _ => false,
+    };
+
+    let statement_start = match previous {
+        None => true,
+        Some(Token::Punct(p)) => {
+            matches!(p, Punct::SemiColon | Punct::OpenBrace | Punct::CloseBrace)
+                || after_line_break
+                    && matches!(p, Punct::CloseBracket | Punct::DoublePlus | Punct::DoubleDash)
+        }
+        Some(
+            Token::Ident(_) | Token::Number(_) | Token::String(_) | Token::Boolean(_) | Token::Null,
+        ) => after_line_break,
+        _ => false,
+    };
+
+    is_assert
+        && statement_start
+        && matches!(
+            items.get(i + 1).map(|i| &i.token),
+            Some(Token::Punct(Punct::OpenParen))
+        )
+}
+
+/// Returns the index after the call starting at `i`, including a trailing semicolon.
+fn skip_call(items: &[ress::Item<&str>], mut i: usize) -> usize {
+    let mut depth = 0;
+
+    i += 1;
+
+    while i < items.len() {
+        match items[i].token {
+            Token::Punct(Punct::OpenParen) => depth += 1,
+            Token::Punct(Punct::CloseParen) => {
+                depth -= 1;
+                if depth == 0 {
+                    i += 1;
+                    break;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    if let Some(Token::Punct(Punct::SemiColon)) = items.get(i).map(|i| &i.token) {
+        i += 1;
+    }
+
+    i
+}
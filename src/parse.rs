@@ -1,15 +1,18 @@
-use std::{iter::once, string::ParseError};
-
 use owo_colors::OwoColorize;
 
-use crate::{utils::StartsWithAt, Attribute, ClassList, Component, Dialect, Element, Id, Node};
+use crate::{
+    character_references::NAMED_CHARACTER_REFERENCES,
+    utils::{children_of, js_string_literal},
+    Attribute, ClassList, Component, Dialect, Directive, Element, Id, Node, Span, StaticAttribute,
+    Transition, TransitionKind,
+};
 
 pub fn parse_full(
     input: &str,
     component_name: &str,
     dialect: Dialect,
 ) -> Result<Component, CompilerError> {
-    let children = parse(input, 0, input.len())?;
+    let mut children = parse(input, 0, input.len())?;
 
     if component_name == "Children" {
         return Err(CompilerError {
@@ -18,12 +21,15 @@ pub fn parse_full(
         });
     }
 
+    set_span_component(&mut children, component_name);
+
     Ok(Component {
         name: component_name.to_string(),
         dialect,
         props: vec![],
         children,
         recursive: false,
+        source: input.to_string(),
     })
 }
 
@@ -32,6 +38,7 @@ pub static VOID_ELEMENTS: [&str; 13] = [
     "source",
 ];
 
+/// A byte offset into the component source.
 type Position = usize;
 
 #[derive(Debug)]
@@ -51,87 +58,198 @@ impl CompilerError {
     }
 }
 
+/// Spans are made without knowing which component is being parsed, so it's filled in once it's done.
+fn set_span_component(nodes: &mut [Node], component: &str) {
+    let set_attributes = |attributes: &mut [Attribute]| {
+        for attr in attributes {
+            if let Attribute::Reactive(ra) = attr {
+                ra.span.component = component.to_string();
+            }
+        }
+    };
+
+    for node in nodes {
+        match node {
+            Node::ScriptTag(script) => script.span.component = component.to_string(),
+            Node::ReactiveText(_, span) => span.component = component.to_string(),
+            Node::Element(e) => set_attributes(&mut e.attributes),
+            Node::ComponentHole {
+                props, children, ..
+            } => {
+                set_attributes(props);
+
+                if let Some(children) = children {
+                    set_span_component(children, component);
+                }
+            }
+            _ => {}
+        }
+
+        for children in children_of(node) {
+            set_span_component(children, component);
+        }
+    }
+}
+
 fn format_position(input: &str, position: Position) -> String {
-    let line = input.chars().take(position).filter(|c| *c == '\n').count() + 1;
+    let mut position = position.min(input.len());
 
-    let mut column = 0;
+    while !input.is_char_boundary(position) {
+        position -= 1;
+    }
+
+    let before = &input[..position];
 
-    let mut i = position;
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let column = before[line_start..].chars().count() + 1;
 
-    if i >= input.chars().count() {
-        i = input.chars().count() - 1;
+    format!("[{}:{}] ", line, column)
+}
+
+/// Walks the source one character at a time. Positions are byte offsets, so every lookup and
+/// slice is constant time and spans always fall on character boundaries.
+#[derive(Clone, Copy)]
+struct Cursor<'a> {
+    input: &'a str,
+    pos: Position,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(input: &'a str, pos: Position) -> Self {
+        Cursor { input, pos }
     }
 
-    while i > 0 {
-        i -= 1;
-        column += 1;
-        if input.chars().nth(i).unwrap() == '\n' {
-            break;
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.pos >= self.input.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        self.rest().starts_with(s)
+    }
+
+    /// Consumes `s` if the remaining input starts with it.
+    fn eat(&mut self, s: &str) -> bool {
+        if self.starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
         }
     }
 
-    format!("[{}:{}] ", line, column)
+    /// Consumes characters while `f` holds and returns them.
+    fn eat_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+        let start = self.pos;
+        let len = self.rest().find(|c| !f(c)).unwrap_or(self.rest().len());
+        self.pos += len;
+        &self.input[start..self.pos]
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.eat_while(char::is_whitespace);
+    }
 }
 
 fn push_text(text: &mut String, nodes: &mut Vec<Node>) {
-    let node_text = text.chars().fold("".to_string(), |acc, c| {
-        if let Some(last) = acc.chars().last() {
-            if last.is_whitespace() && c.is_whitespace() {
-                return acc;
-            }
-        }
+    let mut node_text = String::with_capacity(text.len());
 
-        if c.is_whitespace() {
-            return acc + " ";
+    for c in text.chars() {
+        if !c.is_whitespace() {
+            node_text.push(c);
+        } else if !node_text.ends_with(' ') {
+            node_text.push(' ');
         }
-
-        format!("{}{}", acc, c)
-    });
+    }
 
     if !node_text.is_empty() {
-        nodes.push(Node::Text(node_text));
+        nodes.push(Node::Text(decode_character_references(&node_text)));
     }
 
     text.clear();
 }
 
-fn parse(input: &str, start: usize, end: usize) -> Result<Vec<Node>, CompilerError> {
-    let mut pos = start;
+fn parse(input: &str, start: Position, end: Position) -> Result<Vec<Node>, CompilerError> {
+    parse_nodes(input, start, end, false)
+}
+
+/// Parses the body of a block, which unlike other content may start with `{@const}` tags.
+fn parse_block(input: &str, start: Position, end: Position) -> Result<Vec<Node>, CompilerError> {
+    parse_nodes(input, start, end, true)
+}
+
+fn parse_nodes(
+    input: &str,
+    start: Position,
+    end: Position,
+    allow_consts: bool,
+) -> Result<Vec<Node>, CompilerError> {
+    let mut cursor = Cursor::new(input, start);
 
     let mut nodes = vec![];
 
     let mut text = String::new();
 
-    while pos < end {
-        skip_comments(input, &mut pos);
+    while cursor.pos < end {
+        skip_comments(&mut cursor);
 
-        if pos >= input.len() {
+        if cursor.pos >= end {
             break;
         }
 
-        let c = input.chars().nth(pos).unwrap();
-
-        if c == '<' {
+        if cursor.starts_with("<") {
             push_text(&mut text, &mut nodes);
-            nodes.push(parse_elem(input, &mut pos)?);
-        } else if c == '{' {
+            nodes.push(parse_elem(&mut cursor)?);
+        } else if cursor.starts_with("{") {
             push_text(&mut text, &mut nodes);
 
-            let inner = curly_inner(input, &mut pos);
+            let opening_pos = cursor.pos;
+            let inner = curly_inner(&mut cursor);
 
             if inner.starts_with("#") {
-                nodes.push(handle_expression(input, &mut pos, inner)?);
-            } else if inner.starts_with("/") {
-                panic!();
+                nodes.push(handle_expression(&mut cursor, inner)?);
+            } else if is_keyword_tag(&inner, "@const") {
+                let at_top = nodes.iter().all(|n| match n {
+                    Node::Const { .. } => true,
+                    Node::Text(t) => t.trim().is_empty(),
+                    _ => false,
+                });
+
+                if !allow_consts || !at_top {
+                    return Err(CompilerError {
+                        position: opening_pos,
+                        message: "{@const} is only allowed at the top of a block".to_string(),
+                    });
+                }
+
+                // Only whitespace can be between the tags, which shouldn't end up in the output.
+                nodes.retain(|n| matches!(n, Node::Const { .. }));
+                nodes.push(parse_const(&inner["@const".len()..], opening_pos)?);
+            } else if inner.starts_with("/") || inner.starts_with(":") {
+                return Err(CompilerError {
+                    position: opening_pos,
+                    message: format!("Unexpected {{{}}}", inner),
+                });
             } else {
-                nodes.push(Node::ReactiveText(inner));
+                nodes.push(Node::ReactiveText(inner, span(opening_pos + 1)));
             }
-
-            pos -= 1;
-        } else {
+        } else if let Some(c) = cursor.bump() {
             text.push(c);
         }
-        pos += 1;
     }
 
     push_text(&mut text, &mut nodes);
@@ -139,13 +257,13 @@ fn parse(input: &str, start: usize, end: usize) -> Result<Vec<Node>, CompilerErr
     Ok(nodes)
 }
 
-fn handle_expression(input: &str, pos: &mut usize, opening: String) -> Result<Node, CompilerError> {
+fn handle_expression(cursor: &mut Cursor, opening: String) -> Result<Node, CompilerError> {
     assert!(opening.starts_with("#"));
-    let opening_tokens = tokenize_expression(opening);
+    let opening_tokens = tokenize_expression(&opening);
 
     if opening_tokens.len() < 2 {
         return Err(CompilerError {
-            position: *pos,
+            position: cursor.pos,
             message: "Expected expression".to_string(),
         });
     }
@@ -154,12 +272,12 @@ fn handle_expression(input: &str, pos: &mut usize, opening: String) -> Result<No
         "if" => {
             let expression = opening_tokens[1..].join(" ");
 
-            let opening_pos = *pos;
-            let closing_pos = search_for_closing(input, Some("{#if"), "{/if}", pos)?;
+            let opening_pos = cursor.pos;
+            let closing_pos = search_for_closing(cursor, Some("{#if"), "{/if}")?;
 
             // TODO: else
 
-            let children = parse(input, opening_pos, closing_pos)?;
+            let children = parse_block(cursor.input, opening_pos, closing_pos)?;
 
             Ok(Node::ConditionalElements {
                 condition: expression,
@@ -167,55 +285,247 @@ fn handle_expression(input: &str, pos: &mut usize, opening: String) -> Result<No
             })
         }
         "for" => {
-            if opening_tokens.len() < 4 || opening_tokens[2] != "in" {
-                return Err(CompilerError {
-                    position: *pos,
-                    message: "Invalid for expression".to_string(),
-                });
+            let invalid = || CompilerError {
+                position: cursor.pos,
+                message: "Invalid for expression".to_string(),
+            };
+
+            let header = opening["#for".len()..].trim();
+
+            let in_pos = find_top_level(header, |rest| {
+                let after = rest.trim_start();
+                rest.starts_with(char::is_whitespace)
+                    && after.starts_with("in")
+                    && after["in".len()..].starts_with(char::is_whitespace)
+            })
+            .ok_or_else(invalid)?;
+
+            let binding = header[..in_pos].trim();
+            let mut iteratable = header[in_pos..].trim_start()["in".len()..].trim();
+
+            let (iterator_variable, index_variable) =
+                match find_top_level(binding, |rest| rest.starts_with(',')) {
+                    Some(comma) => (
+                        binding[..comma].trim(),
+                        Some(binding[comma + 1..].trim().to_string()),
+                    ),
+                    None => (binding, None),
+                };
+
+            if iterator_variable.is_empty()
+                || iteratable.is_empty()
+                || index_variable.as_deref().is_some_and(|i| !is_identifier(i))
+            {
+                return Err(invalid());
             }
 
-            let iterator_variable = opening_tokens[1].clone();
-
-            let mut iteratable = opening_tokens[3..].join(" ");
             let mut reactive_list = false;
 
-            if opening_tokens[3] == "$lstate" {
-                reactive_list = true;
-                iteratable = opening_tokens[4..].join(" ");
+            if let Some(list) = iteratable.strip_prefix("$lstate") {
+                if list.starts_with(char::is_whitespace) {
+                    reactive_list = true;
+                    iteratable = list.trim();
+                }
             }
 
-            let opening_pos = *pos;
-            let closing_pos = search_for_closing(input, Some("{#for"), "{/for}", pos)?;
+            let opening_pos = cursor.pos;
+            let closing_pos = search_for_closing(cursor, Some("{#for"), "{/for}")?;
+
+            let mut branches = split_branches(cursor.input, opening_pos, closing_pos).into_iter();
+
+            let (_, start, end) = branches.next().unwrap();
+            let children = parse_block(cursor.input, start, end)?;
+
+            let mut empty = vec![];
+
+            for (i, (header, start, end)) in branches.enumerate() {
+                if header != ":empty" || i > 0 {
+                    return Err(CompilerError {
+                        position: start,
+                        message: format!("Unexpected {{{}}} in for block", header),
+                    });
+                }
 
-            let children = parse(input, opening_pos, closing_pos)?;
+                empty = parse_block(cursor.input, start, end)?;
+            }
 
             Ok(Node::Loop {
-                iterator_variable,
+                iterator_variable: iterator_variable.to_string(),
+                index_variable,
                 reactive_list,
-                iteratable,
+                iteratable: iteratable.to_string(),
                 children,
+                empty,
+            })
+        }
+        "key" => {
+            let key = opening["#key".len()..].trim().to_string();
+
+            let opening_pos = cursor.pos;
+            let closing_pos = search_for_closing(cursor, Some("{#key"), "{/key}")?;
+
+            let children = parse_block(cursor.input, opening_pos, closing_pos)?;
+
+            Ok(Node::Key { key, children })
+        }
+        "await" => {
+            let promise = opening["#await".len()..].trim().to_string();
+
+            let opening_pos = cursor.pos;
+            let closing_pos = search_for_closing(cursor, Some("{#await"), "{/await}")?;
+
+            let mut branches = split_branches(cursor.input, opening_pos, closing_pos).into_iter();
+
+            let (_, start, end) = branches.next().unwrap();
+            let pending = parse_block(cursor.input, start, end)?;
+
+            let mut then = None;
+            let mut catch = None;
+
+            for (header, start, end) in branches {
+                let (keyword, pattern) = match header.split_once(char::is_whitespace) {
+                    Some((keyword, pattern)) => (keyword, Some(pattern.trim().to_string())),
+                    None => (header.as_str(), None),
+                };
+
+                let branch = match keyword {
+                    ":then" if then.is_none() && catch.is_none() => &mut then,
+                    ":catch" if catch.is_none() => &mut catch,
+                    _ => {
+                        return Err(CompilerError {
+                            position: start,
+                            message: format!("Unexpected {{{}}} in await block", header),
+                        })
+                    }
+                };
+
+                *branch = Some(crate::AwaitBranch {
+                    pattern,
+                    children: parse_block(cursor.input, start, end)?,
+                });
+            }
+
+            Ok(Node::Await {
+                promise,
+                pending,
+                then,
+                catch,
             })
         }
         _ => Err(CompilerError {
-            position: *pos,
+            position: cursor.pos,
             message: format!("Unknown directive {}", opening_tokens[0]),
         }),
     }
 }
 
+/// Whether the inside of a `{...}` tag is the given keyword, optionally followed by more.
+fn is_keyword_tag(inner: &str, keyword: &str) -> bool {
+    inner
+        .strip_prefix(keyword)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Parses the `name = value` part of a `{@const}` tag.
+fn parse_const(declaration: &str, position: Position) -> Result<Node, CompilerError> {
+    let eq = find_top_level(declaration, |rest| {
+        rest.starts_with('=') && !rest.starts_with("==") && !rest.starts_with("=>")
+    });
+
+    let (name, value) = match eq {
+        Some(eq) => (declaration[..eq].trim(), declaration[eq + 1..].trim()),
+        None => ("", ""),
+    };
+
+    if !is_identifier(name) || value.is_empty() {
+        return Err(CompilerError {
+            position,
+            message: "Invalid {@const} declaration, expected {@const name = value}".to_string(),
+        });
+    }
+
+    Ok(Node::Const {
+        name: name.to_string(),
+        value: value.to_string(),
+    })
+}
+
+/// Finds the first position in `text` that isn't nested inside brackets and where `separator`
+/// matches the rest of the text.
+fn find_top_level(text: &str, separator: impl Fn(&str) -> bool) -> Option<usize> {
+    let mut depth = 0;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            _ if depth == 0 && separator(&text[i..]) => return Some(i),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Splits the body of a block on its `{:branch}` tags, ignoring the ones inside nested blocks.
+/// Returns the tag and the range of each branch, the first has an empty tag.
+fn split_branches(
+    input: &str,
+    start: Position,
+    end: Position,
+) -> Vec<(String, Position, Position)> {
+    let mut branches = vec![];
+
+    let mut header = String::new();
+    let mut branch_start = start;
+
+    let mut depth = 0;
+
+    let mut cursor = Cursor::new(input, start);
+
+    while cursor.pos < end {
+        if cursor.starts_with("{#") {
+            depth += 1;
+        } else if cursor.starts_with("{/") {
+            depth -= 1;
+        } else if depth == 0 && cursor.starts_with("{:") {
+            let tag_start = cursor.pos;
+            let inner = curly_inner(&mut cursor);
+
+            branches.push((header, branch_start, tag_start));
+
+            header = inner.trim().to_string();
+            branch_start = cursor.pos;
+
+            continue;
+        }
+
+        cursor.bump();
+    }
+
+    branches.push((header, branch_start, end));
+
+    branches
+}
+
+/// Moves the cursor past the matching `needle` and returns where it started.
 fn search_for_closing(
-    haystack: &str,
+    cursor: &mut Cursor,
     depth_increase: Option<&str>,
     needle: &str,
-    pos: &mut usize,
-) -> Result<usize, CompilerError> {
+) -> Result<Position, CompilerError> {
     let mut depth = 0;
 
-    while *pos < haystack.len() {
-        if haystack.starts_with_at(*pos, needle) {
+    while !cursor.is_at_end() {
+        if cursor.starts_with(needle) {
             if depth == 0 {
-                let at = *pos;
-                *pos += needle.len();
+                let at = cursor.pos;
+                cursor.pos += needle.len();
                 return Ok(at);
             } else {
                 depth -= 1;
@@ -223,44 +533,45 @@ fn search_for_closing(
         }
 
         if let Some(depth_increase) = depth_increase {
-            if haystack.starts_with_at(*pos, depth_increase) {
+            if cursor.starts_with(depth_increase) {
                 depth += 1;
             }
         }
 
-        *pos += 1;
+        cursor.bump();
     }
 
     Err(CompilerError {
-        position: *pos,
+        position: cursor.pos,
         message: format!("expected {}", needle),
     })
 }
 
-fn tokenize_expression(expr: String) -> Vec<String> {
+fn tokenize_expression(expr: &str) -> Vec<String> {
     let mut tokens = vec![];
 
     let mut token = String::new();
 
-    let mut pos = 1;
-    while pos < expr.len() {
-        let c = expr.chars().nth(pos).unwrap();
+    // Skips the leading `#`.
+    let mut cursor = Cursor::new(expr, 1);
 
+    while let Some(c) = cursor.peek() {
         if c.is_whitespace() {
             if !token.is_empty() {
                 tokens.push(token.clone());
                 token.clear();
             }
         } else if c == '{' {
-            let inner = curly_inner(&expr, &mut pos);
+            let inner = curly_inner(&mut cursor);
             if !inner.is_empty() {
                 tokens.push(inner);
             }
+            continue;
         } else {
             token.push(c);
         }
 
-        pos += 1;
+        cursor.bump();
     }
 
     if !token.is_empty() {
@@ -270,84 +581,93 @@ fn tokenize_expression(expr: String) -> Vec<String> {
     tokens
 }
 
-fn curly_inner(input: &str, pos: &mut usize) -> String {
-    *pos += 1;
+/// Returns the contents of the braces at the cursor and moves past the closing brace.
+fn curly_inner(cursor: &mut Cursor) -> String {
+    cursor.bump();
 
-    let start = *pos;
+    let start = cursor.pos;
 
     let mut depth = 1;
 
-    while *pos < input.len() {
-        if input.starts_with_at(*pos, "{") {
+    while let Some(c) = cursor.bump() {
+        if c == '{' {
             depth += 1;
-        } else if input.starts_with_at(*pos, "}") {
+        } else if c == '}' {
             depth -= 1;
-        }
-
-        *pos += 1;
 
-        if depth == 0 {
-            break;
+            if depth == 0 {
+                return cursor.input[start..cursor.pos - 1].to_string();
+            }
         }
     }
 
-    input.chars().skip(start).take((*pos - 1) - start).collect()
+    cursor.input[start..].to_string()
 }
 
 #[derive(Eq, PartialEq, Debug)]
 enum AttrParsingContext {
     None,
     Quotes,
+    SingleQuotes,
     Curly,
 }
 
-fn skip_comments(input: &str, pos: &mut usize) {
-    let mut in_comment = false;
-
-    if input.starts_with_at(*pos, "<!--") {
-        in_comment = true;
-        *pos += 4;
-    }
-
-    while *pos < input.len() && in_comment {
-        if in_comment {
-            if input.starts_with_at(*pos, "-->") {
-                *pos += 3;
-                return;
-            }
-        }
-
-        *pos += 1;
+fn skip_comments(cursor: &mut Cursor) {
+    if cursor.eat("<!--") {
+        cursor.pos = match cursor.rest().find("-->") {
+            Some(i) => cursor.pos + i + 3,
+            None => cursor.input.len(),
+        };
     }
 }
 
-fn parse_elem(input: &str, pos: &mut usize) -> Result<Node, CompilerError> {
-    let starting_pos = *pos;
+fn parse_elem(cursor: &mut Cursor) -> Result<Node, CompilerError> {
+    let input = cursor.input;
+    let starting_pos = cursor.pos;
 
-    *pos += 1;
-    skip_whitespace(input, pos);
+    cursor.bump();
+    cursor.skip_whitespace();
 
-    if input.starts_with_at(*pos, "/") {
-        *pos += 1;
-        let name = grab_alphanum_token(input, pos);
+    if cursor.eat("/") {
+        let name = grab_tag_name(cursor);
         if VOID_ELEMENTS.contains(&name.as_str()) {
             return Err(CompilerError {
-                position: *pos,
+                position: cursor.pos,
                 message: format!("Element <{name}> cannot have a closing tag."),
             });
         }
 
         return Err(CompilerError {
-            position: *pos,
+            position: cursor.pos,
             message: "Unexpected closing tag".to_string(),
         });
     }
 
-    let name = grab_alphanum_token(input, pos);
+    let name = grab_tag_name(cursor);
+
+    // A `<>...</>` fragment groups its children without an element of its own.
+    if name.is_empty() && cursor.eat(">") {
+        let (end_pos, closing_tag_pos) = find_closing(input, "", cursor.pos)?;
+        let children = parse(input, cursor.pos, closing_tag_pos)?;
+
+        cursor.pos = end_pos;
+
+        return Ok(Node::Element(Element {
+            name,
+            id: None,
+            id_classes: vec![],
+            classes: None,
+            class_directives: vec![],
+            style_directives: vec![],
+            transitions: vec![],
+            attributes: vec![],
+            children,
+        }));
+    }
 
     if name.is_empty() {
         return Err(CompilerError {
-            position: *pos,
+            position: cursor.pos,
             message: "Expected element name".to_string(),
         });
     }
@@ -359,44 +679,53 @@ fn parse_elem(input: &str, pos: &mut usize) -> Result<Node, CompilerError> {
     let mut unparsed_attributes = vec![];
 
     let mut token = String::new();
+    let mut token_start = cursor.pos;
 
     let mut attr_context = AttrParsingContext::None;
 
     let mut depth = 0;
 
-    while *pos < input.len()
-        && !(attr_context == AttrParsingContext::None && input.starts_with_at(*pos, ">"))
-    {
-        let c = input.chars().nth(*pos).unwrap();
-
+    while let Some(c) = cursor.peek() {
         if attr_context == AttrParsingContext::None {
+            if c == '>' {
+                break;
+            }
+
             if (c.is_whitespace() || c == '=') && !token.is_empty() {
-                unparsed_attributes.push(token.clone());
+                unparsed_attributes.push((token.clone(), token_start));
                 token.clear();
             }
 
-            skip_whitespace(input, pos);
+            cursor.skip_whitespace();
 
-            let c = input.chars().skip(*pos).next().unwrap();
+            let Some(c) = cursor.peek() else {
+                break;
+            };
 
             if c == '"' {
                 attr_context = AttrParsingContext::Quotes;
+            } else if c == '\'' {
+                attr_context = AttrParsingContext::SingleQuotes;
             } else if c == '{' {
                 attr_context = AttrParsingContext::Curly;
             }
 
-            if input.starts_with_at(*pos, "/>") {
+            if cursor.starts_with("/>") {
                 no_closer = true;
                 break;
             }
 
-            if input.starts_with_at(*pos, ">") {
+            if c == '>' {
                 break;
             }
 
             if c == '=' {
-                unparsed_attributes.push("=".to_string());
+                unparsed_attributes.push(("=".to_string(), cursor.pos));
             } else {
+                if token.is_empty() {
+                    token_start = cursor.pos;
+                }
+
                 token.push(c);
             }
         } else {
@@ -409,6 +738,11 @@ fn parse_elem(input: &str, pos: &mut usize) -> Result<Node, CompilerError> {
                         attr_context = AttrParsingContext::None;
                     }
                 }
+                AttrParsingContext::SingleQuotes => {
+                    if c == '\'' {
+                        attr_context = AttrParsingContext::None;
+                    }
+                }
                 AttrParsingContext::Curly => {
                     if c == '}' {
                         if depth == 0 {
@@ -423,11 +757,11 @@ fn parse_elem(input: &str, pos: &mut usize) -> Result<Node, CompilerError> {
             }
         }
 
-        *pos += 1;
+        cursor.bump();
     }
 
     if !token.is_empty() {
-        unparsed_attributes.push(token);
+        unparsed_attributes.push((token, token_start));
     }
 
     let mut attributes = vec![];
@@ -440,9 +774,31 @@ fn parse_elem(input: &str, pos: &mut usize) -> Result<Node, CompilerError> {
             //         message: "Expected attribute value".to_string(),
             //     });
             // }
-            let name = unparsed_attributes.remove(0);
+            let (name, _) = unparsed_attributes.remove(0);
+
+            if let Some(spread) = name.strip_prefix("{...") {
+                let Some(value) = spread.strip_suffix("}") else {
+                    return Err(CompilerError {
+                        position: cursor.pos,
+                        message: "Expected }".to_string(),
+                    });
+                };
+
+                attributes.push(Attribute::Spread(crate::SpreadAttribute {
+                    value: value.trim().to_string(),
+                }));
+
+                continue;
+            }
+
+            if !is_valid_attribute_name(&name) {
+                return Err(CompilerError {
+                    position: cursor.pos,
+                    message: format!("Invalid attribute name {:?}", name),
+                });
+            }
 
-            if unparsed_attributes.is_empty() || unparsed_attributes[0] != "=" {
+            if unparsed_attributes.is_empty() || unparsed_attributes[0].0 != "=" {
                 attributes.push(Attribute::Static(crate::StaticAttribute {
                     name,
                     value: None,
@@ -452,105 +808,110 @@ fn parse_elem(input: &str, pos: &mut usize) -> Result<Node, CompilerError> {
             }
 
             let _eq = unparsed_attributes.remove(0);
-            let value = unparsed_attributes.remove(0);
 
-            if value.starts_with("\"") {
-                if !value.ends_with("\"") && value.len() > 1 {
+            if unparsed_attributes.is_empty() {
+                return Err(CompilerError {
+                    position: cursor.pos,
+                    message: format!("Expected value for attribute {}", name),
+                });
+            }
+
+            let (value, value_start) = unparsed_attributes.remove(0);
+
+            if value.starts_with('"') || value.starts_with('\'') {
+                let quote = &value[0..1];
+
+                if !value.ends_with(quote) || value.len() < 2 {
                     if value.ends_with(",") {
                         return Err(CompilerError {
-                            position: *pos,
+                            position: cursor.pos,
                             message: "Commas should not be used to separate attribute arguments"
                                 .to_string(),
                         });
                     }
 
                     return Err(CompilerError {
-                        position: *pos,
-                        message: "Expected \"".to_string(),
+                        position: cursor.pos,
+                        message: format!("Expected {}", quote),
                     });
                 }
 
                 attributes.push(Attribute::Static(crate::StaticAttribute {
                     name,
-                    value: Some(value.chars().skip(1).take(value.len() - 2).collect()),
+                    value: Some(decode_character_references(&value[1..value.len() - 1])),
                 }));
             } else if value.starts_with("{") {
                 if !value.ends_with("}") {
                     return Err(CompilerError {
-                        position: *pos,
+                        position: cursor.pos,
                         message: "Expected }".to_string(),
                     });
                 }
 
                 attributes.push(Attribute::Reactive(crate::ReactiveAttribute {
                     name,
-                    value: value.chars().skip(1).take(value.len() - 2).collect(),
+                    value: value[1..value.len() - 1].to_string(),
+                    span: span(value_start + 1),
                 }));
-            } else {
+            } else if value.chars().any(|c| "\"'=<>`".contains(c)) {
                 return Err(CompilerError {
-                    position: *pos,
+                    position: cursor.pos,
                     message: "Invalid attribute value".to_string(),
                 });
+            } else {
+                attributes.push(Attribute::Static(crate::StaticAttribute {
+                    name,
+                    value: Some(decode_character_references(&value)),
+                }));
             }
         }
     }
 
-    if *pos == input.len() {
+    if cursor.is_at_end() {
         return Err(CompilerError {
-            position: *pos,
+            position: cursor.pos,
             message: format!("Expected >. {} tag was never closed", name),
         });
     }
 
-    *pos += 1;
+    cursor.eat("/");
+    cursor.eat(">");
 
     let mut children = vec![];
 
-    let opening_tag_end = *pos;
-    let mut closing_tag_pos = 0;
+    let opening_tag_end = cursor.pos;
+    let mut closing_tag_pos = opening_tag_end;
 
     if !no_closer {
-        let (end_pos, closing_tag_pos_) = find_closing(input, &name, *pos)?;
+        let (end_pos, closing_tag_pos_) = find_closing(input, &name, cursor.pos)?;
 
         closing_tag_pos = closing_tag_pos_;
 
         if children_are_html {
-            children = parse(input, *pos, closing_tag_pos)?;
+            children = parse(input, opening_tag_end, closing_tag_pos)?;
         } else {
-            let inner = &input
-                .chars()
-                .skip(*pos)
-                .take(closing_tag_pos - *pos)
-                .collect::<String>();
+            let inner = &input[opening_tag_end..closing_tag_pos];
             // TODO: Be smart about JS and CSS.
             if !inner.is_empty() {
                 children.push(Node::Text(inner.to_string()));
             }
         }
 
-        *pos = end_pos;
+        cursor.pos = end_pos;
     }
 
     if name == "script" {
-        let js = input
-            .chars()
-            .skip(opening_tag_end)
-            .take(closing_tag_pos - opening_tag_end)
-            .collect();
+        let js = input[opening_tag_end..closing_tag_pos].to_string();
         return Ok(Node::ScriptTag(crate::ScriptTag {
             attributes,
             code: js,
+            span: span(opening_tag_end),
         }));
     }
 
     if name == "style" {
-        let css: String = input
-            .to_string()
-            .chars()
-            .skip(opening_tag_end)
-            .take(closing_tag_pos - opening_tag_end)
-            .collect();
-        let css = crate::css::parse(css.as_str()).map_err(|e| CompilerError {
+        let css = &input[opening_tag_end..closing_tag_pos];
+        let css = crate::css::parse(css).map_err(|e| CompilerError {
             position: e.location + opening_tag_end,
             message: format!("{}: {}", "CSS syntax error".red(), e.message),
         })?;
@@ -558,6 +919,25 @@ fn parse_elem(input: &str, pos: &mut usize) -> Result<Node, CompilerError> {
     }
 
     if name.chars().next().unwrap().is_uppercase() {
+        if let Some(directive) = attributes.iter().find(|a| is_directive(a.name())) {
+            return Err(CompilerError {
+                position: starting_pos,
+                message: format!(
+                    "Directive {} can only be used on elements, not components.",
+                    directive.name()
+                ),
+            });
+        }
+
+        if name == "ErrorBoundary" {
+            return error_boundary(&attributes, children, starting_pos).map_err(|message| {
+                CompilerError {
+                    position: starting_pos,
+                    message,
+                }
+            });
+        }
+
         return Ok(Node::ComponentHole {
             name,
             position: starting_pos,
@@ -573,6 +953,7 @@ fn parse_elem(input: &str, pos: &mut usize) -> Result<Node, CompilerError> {
         .and_then(|a| match a {
             Attribute::Static(sa) => Some(Id::Static(sa.value.clone()?)),
             Attribute::Reactive(ra) => Some(Id::Reactive(ra.value.clone())),
+            Attribute::Spread(_) => None,
         });
 
     let classes = attributes
@@ -588,26 +969,54 @@ fn parse_elem(input: &str, pos: &mut usize) -> Result<Node, CompilerError> {
                     .collect(),
             )),
             Attribute::Reactive(ra) => Some(ClassList::Reactive(ra.value.clone())),
+            Attribute::Spread(_) => None,
         });
 
+    let directives = |prefix| {
+        attributes
+            .iter()
+            .filter_map(|a| directive(a, prefix))
+            .collect::<Result<Vec<_>, String>>()
+            .map_err(|message| CompilerError {
+                position: starting_pos,
+                message,
+            })
+    };
+
+    let class_directives = directives("class:")?;
+    let style_directives = directives("style:")?;
+
+    let transitions = attributes
+        .iter()
+        .filter_map(transition)
+        .collect::<Result<Vec<_>, String>>()
+        .map_err(|message| CompilerError {
+            position: starting_pos,
+            message,
+        })?;
+
     let attributes = attributes
         .into_iter()
-        .filter(|a| a.name() != "id" && a.name() != "class")
+        .filter(|a| a.name() != "id" && a.name() != "class" && !is_directive(a.name()))
         .collect();
 
     if let Some(Node::Text(text)) = children.first_mut() {
-        if text.chars().all(char::is_whitespace) {
+        if text.chars().all(|c| c.is_ascii_whitespace()) {
             children.remove(0);
         } else {
-            *text = text.trim_start().to_string();
+            *text = text
+                .trim_start_matches(|c: char| c.is_ascii_whitespace())
+                .to_string();
         }
     }
 
     if let Some(Node::Text(text)) = children.last_mut() {
-        if text.chars().all(char::is_whitespace) {
+        if text.chars().all(|c| c.is_ascii_whitespace()) {
             children.pop();
         } else {
-            *text = text.trim_end().to_string();
+            *text = text
+                .trim_end_matches(|c: char| c.is_ascii_whitespace())
+                .to_string();
         }
     }
 
@@ -619,67 +1028,253 @@ fn parse_elem(input: &str, pos: &mut usize) -> Result<Node, CompilerError> {
     Ok(Node::Element(Element {
         name,
         id,
+        id_classes: vec![],
         classes,
+        class_directives,
+        style_directives,
+        transitions,
         attributes,
         children,
     }))
 }
 
-fn grab_alphanum_token(input: &str, pos: &mut usize) -> String {
-    let start = *pos;
+/// Decodes HTML character references like `&amp;`, `&#39;` and `&#x27;`. Anything that isn't a
+/// recognised reference is left as it is.
+fn decode_character_references(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let reference = rest[1..]
+            .find(';')
+            .map(|end| &rest[1..end + 1])
+            .filter(|r| !r.is_empty() && r.len() <= 32);
+
+        let c = reference.and_then(|r| {
+            if let Some(hex) = r.strip_prefix("#x").or_else(|| r.strip_prefix("#X")) {
+                u32::from_str_radix(hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .map(String::from)
+            } else if let Some(dec) = r.strip_prefix('#') {
+                dec.parse::<u32>()
+                    .ok()
+                    .and_then(char::from_u32)
+                    .map(String::from)
+            } else {
+                NAMED_CHARACTER_REFERENCES
+                    .binary_search_by_key(&r, |(name, _)| name)
+                    .ok()
+                    .map(|i| NAMED_CHARACTER_REFERENCES[i].1.to_string())
+            }
+        });
+
+        match (reference, c) {
+            (Some(r), Some(c)) => {
+                decoded.push_str(&c);
+                rest = &rest[r.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+
+    decoded
+}
+
+fn is_directive(attr_name: &str) -> bool {
+    ["class:", "style:", "transition:", "in:", "out:"]
+        .iter()
+        .any(|prefix| attr_name.starts_with(prefix))
+}
+
+/// Turns a `transition:name={params}`, `in:name` or `out:name` attribute into a transition.
+fn transition(attr: &Attribute) -> Option<Result<Transition, String>> {
+    let (kind, name) = [
+        (TransitionKind::Both, "transition:"),
+        (TransitionKind::In, "in:"),
+        (TransitionKind::Out, "out:"),
+    ]
+    .into_iter()
+    .find_map(|(kind, prefix)| Some((kind, attr.name().strip_prefix(prefix)?)))?;
+
+    if !is_identifier(name) {
+        return Some(Err(format!("Invalid transition name \"{}\".", name)));
+    }
+
+    let params = match attr {
+        Attribute::Static(StaticAttribute { value: None, .. }) => None,
+        Attribute::Reactive(ra) => Some(ra.value.clone()),
+        _ => {
+            return Some(Err(format!(
+            "Parameters of transition {} must be an expression, like {}={{{{ duration: 200 }}}}.",
+            name,
+            attr.name()
+        )))
+        }
+    };
+
+    Some(Ok(Transition {
+        kind,
+        name: name.to_string(),
+        params,
+    }))
+}
+
+/// A span in the component being parsed, see `set_span_component`.
+fn span(start: Position) -> Span {
+    Span {
+        component: String::new(),
+        start,
+    }
+}
 
-    while *pos < input.len() && input.chars().nth(*pos).unwrap().is_alphanumeric() {
-        *pos += 1;
+/// The built-in `<ErrorBoundary>`, whose only attribute is the `fallback` shown when its children
+/// fail.
+fn error_boundary(
+    attributes: &[Attribute],
+    children: Vec<Node>,
+    position: Position,
+) -> Result<Node, String> {
+    let mut fallback = None;
+
+    for attr in attributes {
+        fallback = match attr {
+            Attribute::Static(StaticAttribute {
+                name,
+                value: Some(value),
+            }) if name == "fallback" => Some(js_string_literal(value)),
+            Attribute::Reactive(ra) if ra.name == "fallback" => Some(ra.value.clone()),
+            _ => {
+                return Err(format!(
+                    "Invalid attribute {} on ErrorBoundary, it only takes a fallback.",
+                    attr.name()
+                ))
+            }
+        };
     }
 
-    input.chars().skip(start).take(*pos - start).collect()
+    Ok(Node::ErrorBoundary {
+        position,
+        fallback,
+        children,
+    })
 }
 
-fn skip_whitespace(input: &str, pos: &mut usize) {
-    while *pos < input.len() && input.chars().nth(*pos).unwrap().is_whitespace() {
-        *pos += 1;
+/// Turns a `class:name` or `style:property` attribute into a directive. A directive without a
+/// value uses the variable with the same name, so its name has to be an identifier. Static values
+/// become string literals.
+fn directive(attr: &Attribute, prefix: &str) -> Option<Result<Directive, String>> {
+    let name = attr.name().strip_prefix(prefix)?.to_string();
+
+    let value = match attr {
+        Attribute::Static(sa) => match &sa.value {
+            Some(value) => js_string_literal(value),
+            None if is_identifier(&name) => name.clone(),
+            None => return Some(Err(format!(
+                "Directive {} needs a value, like {}={{value}}, as \"{}\" isn't a variable name.",
+                attr.name(),
+                attr.name(),
+                name
+            ))),
+        },
+        Attribute::Reactive(ra) => ra.value.clone(),
+        Attribute::Spread(_) => return None,
+    };
+
+    Some(Ok(Directive { name, value }))
+}
+
+/// Tag names start with an ASCII letter and run until whitespace, `/` or `>`. This allows custom
+/// elements (`my-widget`) and namespaced names (`svg:rect`).
+fn grab_tag_name(cursor: &mut Cursor) -> String {
+    match cursor.peek() {
+        Some(c) if c.is_ascii_alphabetic() => {}
+        _ => return String::new(),
     }
+
+    cursor.eat_while(|c| !is_tag_name_end(c)).to_string()
+}
+
+fn is_tag_name_end(c: char) -> bool {
+    c.is_whitespace() || c == '/' || c == '>'
+}
+
+/// Whether the tag name at the cursor is exactly `name`, rather than just starting with it.
+fn is_tag_name_at(cursor: &Cursor, name: &str) -> bool {
+    cursor
+        .rest()
+        .strip_prefix(name)
+        .is_some_and(|rest| rest.chars().next().is_none_or(is_tag_name_end))
+}
+
+/// Attribute names can contain anything other than whitespace, quotes, `>`, `/` and `=`, which
+/// covers `data-*`, `aria-*` and namespaced names like `xlink:href`.
+fn is_valid_attribute_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || "\"'>/=<{}".contains(c))
 }
 
-fn find_closing(input: &str, name: &str, mut pos: usize) -> Result<(usize, usize), CompilerError> {
+/// Finds the tag closing the `name` element whose children start at `pos`. Returns the position
+/// after the closing tag and the position of the closing tag itself.
+fn find_closing(
+    input: &str,
+    name: &str,
+    pos: Position,
+) -> Result<(Position, Position), CompilerError> {
     let mut depth = 0;
 
     if VOID_ELEMENTS.contains(&name) {
         return Ok((pos, pos));
     }
 
-    while pos < input.len() {
-        skip_comments(input, &mut pos);
-        if input.starts_with_at(pos, "<") {
-            let mut pos = pos + 1;
-            skip_whitespace(input, &mut pos);
-            if input.starts_with_at(pos, name) {
-                depth += 1;
-            }
-        }
+    let mut cursor = Cursor::new(input, pos);
 
-        if input.starts_with_at(pos, "</") {
-            let closing_tag_pos = pos;
-            let mut pos = pos + 2;
-            skip_whitespace(input, &mut pos);
+    while !cursor.is_at_end() {
+        skip_comments(&mut cursor);
 
-            if input.starts_with_at(pos, name) {
-                pos += name.len();
-                skip_whitespace(input, &mut pos);
+        if cursor.starts_with("</") {
+            let closing_tag_pos = cursor.pos;
+            let mut tag = Cursor::new(input, cursor.pos + 2);
+            tag.skip_whitespace();
 
-                if input.starts_with_at(pos, ">") {
+            if is_tag_name_at(&tag, name) {
+                tag.pos += name.len();
+                tag.skip_whitespace();
+
+                if tag.eat(">") {
                     if depth == 0 {
-                        return Ok((pos, closing_tag_pos));
+                        return Ok((tag.pos, closing_tag_pos));
                     }
                     depth -= 1;
                 }
             }
+        } else if cursor.starts_with("<") {
+            let mut tag = Cursor::new(input, cursor.pos + 1);
+            tag.skip_whitespace();
+
+            if is_tag_name_at(&tag, name) {
+                depth += 1;
+            }
         }
-        pos += 1;
+
+        cursor.bump();
     }
 
     Err(CompilerError {
         position: input.len(),
-        message: format!("Could not find closing tag for {}", name),
+        message: match name {
+            "" => "Could not find closing tag for fragment".to_string(),
+            _ => format!("Could not find closing tag for {}", name),
+        },
     })
 }
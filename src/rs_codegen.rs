@@ -3,8 +3,10 @@ use rustc_lexer::tokenize;
 use crate::{
     codegen::CodegenResult,
     css::{self, StyleSheet},
+    parse::CompilerError,
     utils::{filter_whitespace_nodes, is_all_whitespace, uid},
-    Attribute, ClassList, Component, Element, Node, ReactiveAttribute, ScriptTag, StaticAttribute,
+    Attribute, AwaitBranch, ClassList, Component, Element, Node, ReactiveAttribute, ScriptTag,
+    SpreadAttribute, StaticAttribute, TransitionKind,
 };
 
 impl Node {
@@ -12,11 +14,15 @@ impl Node {
         let mut root = self.clone();
         filter_whitespace_nodes(&mut root);
 
-        let styles = codegen_stylesheet(&stylesheet);
+        let styles = codegen_stylesheet(&stylesheet)?;
 
-        let ctx = CodegenContext { styles: stylesheet };
+        let ctx = CodegenContext {
+            styles: stylesheet,
+            component: String::new(),
+            source: String::new(),
+        };
 
-        let rs = root.gl_codegen(ctx);
+        let rs = root.gl_codegen(ctx)?;
 
         let rs = format!(
             "
@@ -57,23 +63,60 @@ pub fn root(
         Ok(rs)
     }
 
-    fn gl_codegen(&self, ctx: CodegenContext) -> ElementCode {
-        match self {
-            Node::Component(c) => c.rs_codegen(ctx),
-            Node::Element(e) => e.rs_codegen(ctx),
+    fn gl_codegen(&self, ctx: CodegenContext) -> Result<ElementCode, String> {
+        Ok(match self {
+            Node::Component(c) => c.rs_codegen(ctx)?,
+            Node::Element(e) => e.rs_codegen(ctx)?,
             Node::Text(t) => create_text_node(t, ctx),
-            Node::ReactiveText(expr) => create_reactive_text(expr, ctx),
+            Node::ReactiveText(expr, _) => create_reactive_text(expr, ctx),
             Node::Loop {
                 iterator_variable,
+                index_variable,
                 iteratable,
                 reactive_list,
                 children,
-            } => loop_codegen(iterator_variable, iteratable, *reactive_list, children, ctx),
+                empty,
+            } => loop_codegen(
+                iterator_variable,
+                index_variable,
+                iteratable,
+                *reactive_list,
+                children,
+                empty,
+                ctx,
+            )?,
             Node::ConditionalElements {
                 condition,
                 children,
-            } => conditional_codegen(condition, children, ctx),
-            Node::ScriptTag(ScriptTag { attributes, code }) => ElementCode {
+            } => conditional_codegen(condition, children, ctx)?,
+            Node::Key { key, children } => key_codegen(key, children, ctx)?,
+            Node::Await {
+                promise,
+                pending,
+                then,
+                catch,
+            } => await_codegen(promise, pending, then, catch, ctx)?,
+            Node::ErrorBoundary { position, .. } => {
+                return Err(ctx.error_at(
+                    *position,
+                    "ErrorBoundary is not supported by the GL target.",
+                ));
+            }
+            Node::Const { name, value } => {
+                if has_reactive_dependencies(value) {
+                    return Err(ctx.error(&format!(
+                        "{{@const {name} = {value}}} is reactive, which the GL target doesn't support. Use a Reactive in a script instead."
+                    )));
+                }
+
+                ElementCode {
+                    creation_code: format!("let {name} = {value};"),
+                    elem_var_name: "".to_string(),
+                }
+            }
+            Node::ScriptTag(ScriptTag {
+                attributes, code, ..
+            }) => ElementCode {
                 creation_code: code.to_string(),
                 elem_var_name: "".to_string(),
             },
@@ -85,7 +128,7 @@ pub fn root(
                 file_contents,
                 children,
             } => todo!(),
-        }
+        })
     }
 }
 
@@ -98,6 +141,24 @@ fn rename_var(expr: &str) -> String {
 #[derive(Debug, Clone)]
 struct CodegenContext {
     styles: StyleSheet,
+    /// The name of the component being generated, for errors.
+    component: String,
+    /// The contents of its file, for errors at a position in it.
+    source: String,
+}
+
+impl CodegenContext {
+    fn error(&self, message: &str) -> String {
+        format!("\"{}\" {}", self.component, message)
+    }
+
+    fn error_at(&self, position: usize, message: &str) -> String {
+        CompilerError {
+            position,
+            message: message.to_string(),
+        }
+        .format(&self.component, &self.source)
+    }
 }
 
 fn create_text_node(text: &str, ctx: CodegenContext) -> ElementCode {
@@ -107,7 +168,7 @@ fn create_text_node(text: &str, ctx: CodegenContext) -> ElementCode {
         "
         let {elem_var_name} = Text::new(
             gl,
-            \"{text}\".to_string(),
+            {text:?}.to_string(),
             10.0,
             &globals.main_font,
             c(\"ffffff\"),
@@ -123,27 +184,40 @@ fn create_text_node(text: &str, ctx: CodegenContext) -> ElementCode {
     }
 }
 
-fn codegen_stylesheet(ss: &StyleSheet) -> String {
+fn codegen_stylesheet(ss: &StyleSheet) -> CodegenResult {
     let mut code = "let mut stylesheet: HashMap<String, Style> = HashMap::new();".to_string();
 
-    for rule in ss {
-        match rule.selector {
-            css::Selector::Class(ref class) => {
-                code.push_str(&format!(
-                    "
-                    let mut __s = Style::default();
-                    {}
-                    stylesheet.insert(\"{}\".to_string(), __s);
-                ",
-                    codegen_rules(&rule.properties),
-                    class
-                ));
-            }
-            _ => todo!(),
+    // At-rules and selectors other than a class aren't supported, `css_lint` warns about them.
+    for rule in ss.iter().filter(|r| r.at_rules.is_empty()) {
+        if let css::Selector::Class(ref class) = rule.selector {
+            code.push_str(&format!(
+                "
+                let mut __s = Style::default();
+                {}
+                stylesheet.insert(\"{}\".to_string(), __s);
+            ",
+                codegen_rules(&rule.properties)?,
+                class
+            ));
         }
     }
 
-    code
+    Ok(code)
+}
+
+/// Whether an expression references any `$reactive`. A `$` in a string literal doesn't count.
+fn has_reactive_dependencies(expr: &RustExpression) -> bool {
+    let mut tokens = tokenize(expr).map(|token| token.kind).peekable();
+
+    while let Some(kind) = tokens.next() {
+        if kind == rustc_lexer::TokenKind::Dollar
+            && tokens.peek() == Some(&rustc_lexer::TokenKind::Ident)
+        {
+            return true;
+        }
+    }
+
+    false
 }
 
 /// Returns (expr, reactive_clones, subscribes)
@@ -247,11 +321,19 @@ fn reactive_expression(
 
 fn loop_codegen(
     iterator_variable: &RustExpression,
+    index_variable: &Option<String>,
     iteratable: &RustExpression,
     reactive_list: bool,
     children: &Vec<Node>,
+    empty: &Vec<Node>,
     ctx: CodegenContext,
-) -> ElementCode {
+) -> Result<ElementCode, String> {
+    // Items of reactive lists can be removed from anywhere, so each one's index is a `Reactive`
+    // that's updated as the items before it go. The keys are kept in order to work them out.
+    let track_indexes = reactive_list && index_variable.is_some();
+
+    let index_variable = index_variable.clone().unwrap_or("_".to_string());
+
     let create_fn_var_name = format!("__loop{}", uid());
 
     let container_elem_var_name = format!("__loop_container{}", uid());
@@ -262,14 +344,163 @@ fn loop_codegen(
         &"create_list".to_string(),
     );
 
-    let (children_var_names, children_code) = codegen_children(children, ctx);
+    let (children_var_names, children_code) = codegen_children(children, ctx.clone())?;
+
+    let empty_fn_var_name = format!("__loop_empty{}", uid());
+
+    // The empty branch is wrapped in its own phantom element, whose uid is kept so it can be
+    // taken out again when an item is pushed.
+    let (empty_setup, show_empty, empty_clones, hide_empty_on_push, show_empty_on_remove) = if empty
+        .is_empty()
+    {
+        Default::default()
+    } else {
+        let (empty_var_names, empty_code) = codegen_children(empty, ctx)?;
+
+        (
+            format!(
+                "
+                let frame_ref_cpy = frame_ref.clone();
+                let {empty_fn_var_name} = move |gl: &glow::Context, globals: &Globals| {{
+                    let frame_ref = frame_ref_cpy.clone();
+                    {empty_code}
+                    vec![{empty_var_names}]
+                }};
+                let empty_uid = rc_ref_cell(None);
+                let empty_uid_cpy = empty_uid.clone();
+                let {empty_fn_var_name}_cpy = {empty_fn_var_name}.clone();
+                "
+            ),
+            format!(
+                "
+                let children = if children.is_empty() {{
+                    let pe = PhantomElement::new({empty_fn_var_name}_cpy(gl, globals), frame_ref.clone());
+                    empty_uid_cpy.borrow_mut().replace(pe.uid.clone());
+                    vec![pe]
+                }} else {{
+                    children
+                }};
+                "
+            ),
+            format!(
+                "
+                let empty_uid_cpy = empty_uid.clone();
+                let {empty_fn_var_name}_cpy = {empty_fn_var_name}.clone();
+                "
+            ),
+            format!(
+                "
+                if let Some(uid) = empty_uid_cpy.borrow_mut().take() {{
+                    {container_elem_var_name}_cpy.mutate(move |container: &mut PhantomElement| {{
+                        container.children.retain(|c| c.uid != uid);
+                    }});
+                }}
+                "
+            ),
+            format!(
+                "
+                if node_id_map_cpy.as_ref().borrow().is_empty() {{
+                    let empty_uid = empty_uid_cpy.clone();
+                    let {empty_fn_var_name}_cpy = {empty_fn_var_name}_cpy.clone();
+                    let frame_ref = frame_ref_cpy.clone();
+
+                    queue_element(Box::new(move |gl, globals| {{
+                        let pe = PhantomElement::new({empty_fn_var_name}_cpy(gl, globals), frame_ref.clone());
+                        empty_uid.borrow_mut().replace(pe.uid.clone());
+                        pe
+                    }}), {container_elem_var_name}_cpy.clone());
+                }}
+                "
+            ),
+        )
+    };
+
+    let keys_var_name = format!("{create_fn_var_name}_keys");
+    let indexes_var_name = format!("{create_fn_var_name}_indexes");
+
+    let (index_type, track_index, reset_indexes) = if track_indexes {
+        (
+            "Reactive<usize>",
+            format!(
+                "
+                let index = Reactive::new(index);
+                {keys_var_name}_cpy.borrow_mut().push(key);
+                {indexes_var_name}_cpy.borrow_mut().insert(key, index.clone());
+                "
+            ),
+            format!(
+                "
+                {keys_var_name}_cpy.borrow_mut().clear();
+                {indexes_var_name}_cpy.borrow_mut().clear();
+                "
+            ),
+        )
+    } else {
+        ("usize", String::new(), String::new())
+    };
+
+    let (index_maps, index_map_clones, track_pushed_index, pushed_index, shift_indexes) =
+        if track_indexes {
+            (
+                format!(
+                    "
+                    let {keys_var_name}: RcRefCell<Vec<ReactiveListKey>> = rc_ref_cell(vec![]);
+                    let {indexes_var_name}: RcRefCell<HashMap<ReactiveListKey, Reactive<usize>>> =
+                        rc_ref_cell(HashMap::new());
+                    let {keys_var_name}_cpy = {keys_var_name}.clone();
+                    let {indexes_var_name}_cpy = {indexes_var_name}.clone();
+                    "
+                ),
+                format!(
+                    "
+                    let {keys_var_name}_cpy = {keys_var_name}.clone();
+                    let {indexes_var_name}_cpy = {indexes_var_name}.clone();
+                    "
+                ),
+                format!(
+                    "
+                    let index = Reactive::new({keys_var_name}_cpy.borrow().len());
+                    {keys_var_name}_cpy.borrow_mut().push(key);
+                    {indexes_var_name}_cpy.borrow_mut().insert(key, index.clone());
+                    "
+                ),
+                "index.clone()",
+                format!(
+                    "
+                    let mut keys = {keys_var_name}_cpy.borrow_mut();
+                    let mut indexes = {indexes_var_name}_cpy.borrow_mut();
+
+                    if let Some(position) = keys.iter().position(|k| *k == key) {{
+                        keys.remove(position);
+
+                        for (i, k) in keys.iter().enumerate().skip(position) {{
+                            if let Some(index) = indexes.get_mut(k) {{
+                                *index <<= i;
+                            }}
+                        }}
+                    }}
+
+                    indexes.remove(&key);
+                    "
+                ),
+            )
+        } else {
+            (
+                String::new(),
+                String::new(),
+                String::new(),
+                "0",
+                String::new(),
+            )
+        };
 
     let create_fn_call = if reactive_list {
         format!(
             "
             let (key, item) = i;
+            {track_index}
 
-            let children = {create_fn_var_name}_cpy(item, gl, globals);
+            let children = {create_fn_var_name}_cpy(item, index, gl, globals);
             let pe = PhantomElement::new(children, frame_ref.clone());
             
             node_id_map_cpy.as_ref().borrow_mut().insert(key, pe.clone());
@@ -278,18 +509,20 @@ fn loop_codegen(
         "
         )
     } else {
-        format!("{create_fn_var_name}_cpy(i, gl, globals)")
+        format!("{create_fn_var_name}_cpy(i, index, gl, globals)")
     };
 
     let mut creation_code = format!(
         "
             let frame_ref_cpy = frame_ref.clone();
-            let {create_fn_var_name} = move |{iterator_variable}, gl: &glow::Context, globals: &Globals| {{
+            let {create_fn_var_name} = move |{iterator_variable}, {index_variable}: {index_type}, gl: &glow::Context, globals: &Globals| {{
                 let frame_ref = frame_ref_cpy.clone();
                 {children_code}
                 vec![{children_var_names}]
             }};
 
+            {empty_setup}
+
             let {container_elem_var_name} = PhantomElement::new(vec![], frame_ref.clone());
             
             let {container_elem_var_name}_cpy = {container_elem_var_name}.clone();
@@ -302,10 +535,12 @@ fn loop_codegen(
                 let frame_ref = frame_ref_cpy.clone();
 
                 {clones}
+                {reset_indexes}
 
-                let children = iteratable.into_iter().map(|i| {{
+                let children = iteratable.into_iter().enumerate().map(|(index, i)| {{
                     {create_fn_call}
                 }}).flatten().collect::<Vec<Node>>();
+                {show_empty}
 
                 {container_elem_var_name}_cpy.mutate(move |pe: &mut PhantomElement| {{
 
@@ -326,6 +561,7 @@ fn loop_codegen(
             let node_id_map: RcRefCell<HashMap<ReactiveListKey, Node>> =
                 rc_ref_cell(HashMap::new());
             let node_id_map_cpy = node_id_map.clone();
+            {index_maps}
 
             {creation_code}
 
@@ -334,11 +570,15 @@ fn loop_codegen(
             let node_id_map_cpy = node_id_map.clone();
 
             let frame_ref_cpy = frame_ref.clone();
+            {empty_clones}
+            {index_map_clones}
             let push_sub = ({iteratable}).subscribe_to_push(Box::new(move |key, item| {{
                 let frame_ref = frame_ref_cpy.clone();
+                {hide_empty_on_push}
 
                 let key = *key;
                 let item = item.clone();
+                {track_pushed_index}
                 let {create_fn_var_name}_cpy = {create_fn_var_name}_cpy.clone();
                 let node_id_map_cpy = node_id_map_cpy.clone();
 
@@ -347,7 +587,7 @@ fn loop_codegen(
                     Box::new(move |gl: &glow::Context, globals: &mut Globals| {{
                         let frame_ref = frame_ref_cpy.clone();
 
-                        let children = {create_fn_var_name}_cpy(item, gl, globals);
+                        let children = {create_fn_var_name}_cpy(item, {pushed_index}, gl, globals);
                         let pe = PhantomElement::new(children, frame_ref.clone());
                         node_id_map_cpy.as_ref().borrow_mut().insert(key, pe.clone());
                         pe
@@ -359,7 +599,11 @@ fn loop_codegen(
             let {container_elem_var_name}_cpy = {container_elem_var_name}.clone();
             let node_id_map_cpy = node_id_map.clone();
 
+            {empty_clones}
+            {index_map_clones}
+            let frame_ref_cpy = frame_ref.clone();
             let rem_sub = ({iteratable}).subscribe_to_remove(Box::new(move |key, ()| {{
+                {shift_indexes}
                 if let Some(pe) = node_id_map_cpy.as_ref().borrow_mut().remove(&key) {{
                     let uid = pe.uid.clone();
 
@@ -367,6 +611,7 @@ fn loop_codegen(
                         container.children.retain(|c| c.uid != uid);
                     }});
                 }}
+                {show_empty_on_remove}
             }})); 
 
             // TODO: Unsub on cleanup
@@ -374,17 +619,17 @@ fn loop_codegen(
         );
     }
 
-    ElementCode {
+    Ok(ElementCode {
         creation_code,
         elem_var_name: container_elem_var_name,
-    }
+    })
 }
 
 fn conditional_codegen(
     condition: &RustExpression,
     children: &Vec<Node>,
     ctx: CodegenContext,
-) -> ElementCode {
+) -> Result<ElementCode, String> {
     let create_fn_var_name = format!("__cond{}", uid());
 
     let container_elem_var_name = format!("__cond_container{}", uid());
@@ -395,7 +640,7 @@ fn conditional_codegen(
         &"rerun_cond".to_string(),
     );
 
-    let (children_var_names, children_code) = codegen_children(children, ctx);
+    let (children_var_names, children_code) = codegen_children(children, ctx)?;
 
     let creation_code = format!("
 
@@ -451,10 +696,163 @@ fn conditional_codegen(
         {subscribes}
     ");
 
-    ElementCode {
+    Ok(ElementCode {
         creation_code,
         elem_var_name: container_elem_var_name,
-    }
+    })
+}
+
+fn key_codegen(
+    key: &RustExpression,
+    children: &Vec<Node>,
+    ctx: CodegenContext,
+) -> Result<ElementCode, String> {
+    let create_fn_var_name = format!("__key{}", uid());
+
+    let container_elem_var_name = format!("__key_container{}", uid());
+
+    let (key, clones, subscribes) =
+        reactive_expression(key, &container_elem_var_name, &"rerun_key".to_string());
+
+    let (children_var_names, children_code) = codegen_children(children, ctx)?;
+
+    let creation_code = format!("
+
+        let frame_ref_cpy = frame_ref.clone();
+        let {create_fn_var_name} = move |gl: &glow::Context, globals: &mut Globals| {{
+            let frame_ref = frame_ref_cpy.clone();
+
+            {children_code}
+            vec![{children_var_names}]
+        }};
+
+        {clones}
+
+        let prev_key = rc_ref_cell({key});
+
+        let {container_elem_var_name} = PhantomElement::new({create_fn_var_name}(gl, globals), frame_ref.clone());
+
+        let {container_elem_var_name}_cpy = {container_elem_var_name}.clone();
+        let {create_fn_var_name}_cpy = {create_fn_var_name}.clone();
+
+        let frame_ref_cpy = frame_ref.clone();
+        let rerun_key = move || {{
+            let frame_ref = frame_ref_cpy.clone();
+            let {create_fn_var_name}_cpy = {create_fn_var_name}_cpy.clone();
+
+            let key = {key};
+
+            if *prev_key.borrow() == key {{
+                return;
+            }}
+
+            *prev_key.borrow_mut() = key;
+
+            // The old children are dropped and a fresh set is created in their place.
+            {container_elem_var_name}_cpy.mutate(move |pe: &mut PhantomElement| {{
+                // TODO: Cleanup old children
+                pe.children = vec![];
+            }});
+
+            queue_element(Box::new(move |gl, globals| PhantomElement::new({create_fn_var_name}_cpy(gl, globals), frame_ref.clone())), {container_elem_var_name}_cpy.clone());
+        }};
+
+        {subscribes}
+    ");
+
+    Ok(ElementCode {
+        creation_code,
+        elem_var_name: container_elem_var_name,
+    })
+}
+
+/// There are no promises in Rust, so the GL target awaits an `Option<Result<T, E>>` instead, which
+/// is pending while `None`. It's usually reactive, with the branch recreated whenever it changes.
+fn await_codegen(
+    promise: &RustExpression,
+    pending: &Vec<Node>,
+    then: &Option<AwaitBranch>,
+    catch: &Option<AwaitBranch>,
+    ctx: CodegenContext,
+) -> Result<ElementCode, String> {
+    let create_fn_var_name = format!("__await{}", uid());
+
+    let container_elem_var_name = format!("__await_container{}", uid());
+
+    let (promise, clones, subscribes) = reactive_expression(
+        promise,
+        &container_elem_var_name,
+        &"rerun_await".to_string(),
+    );
+
+    let (pending_var_names, pending_code) = codegen_children(pending, ctx.clone())?;
+
+    let branch = |branch: &Option<AwaitBranch>| -> Result<(String, String), String> {
+        Ok(match branch {
+            Some(AwaitBranch { pattern, children }) => {
+                let (var_names, code) = codegen_children(children, ctx.clone())?;
+                (
+                    pattern.clone().unwrap_or("_".to_string()),
+                    format!("{code}\nvec![{var_names}]"),
+                )
+            }
+            None => ("_".to_string(), "vec![]".to_string()),
+        })
+    };
+
+    let (then_pattern, then_code) = branch(then)?;
+    let (catch_pattern, catch_code) = branch(catch)?;
+
+    let creation_code = format!("
+
+        let frame_ref_cpy = frame_ref.clone();
+        let {create_fn_var_name} = move |state: Option<Result<_, _>>, gl: &glow::Context, globals: &mut Globals| {{
+            let frame_ref = frame_ref_cpy.clone();
+
+            match state {{
+                None => {{
+                    {pending_code}
+                    vec![{pending_var_names}]
+                }}
+                Some(Ok({then_pattern})) => {{
+                    {then_code}
+                }}
+                Some(Err({catch_pattern})) => {{
+                    {catch_code}
+                }}
+            }}
+        }};
+
+        {clones}
+
+        let {container_elem_var_name} = PhantomElement::new({create_fn_var_name}({promise}, gl, globals), frame_ref.clone());
+
+        let {container_elem_var_name}_cpy = {container_elem_var_name}.clone();
+        let {create_fn_var_name}_cpy = {create_fn_var_name}.clone();
+
+        let frame_ref_cpy = frame_ref.clone();
+        let rerun_await = move || {{
+            let frame_ref = frame_ref_cpy.clone();
+            let {create_fn_var_name}_cpy = {create_fn_var_name}_cpy.clone();
+
+            let state = {promise};
+
+            // Whichever branch was shown is dropped and the one for the new state created.
+            {container_elem_var_name}_cpy.mutate(move |pe: &mut PhantomElement| {{
+                // TODO: Cleanup old children
+                pe.children = vec![];
+            }});
+
+            queue_element(Box::new(move |gl, globals| PhantomElement::new({create_fn_var_name}_cpy(state.clone(), gl, globals), frame_ref.clone())), {container_elem_var_name}_cpy.clone());
+        }};
+
+        {subscribes}
+    ");
+
+    Ok(ElementCode {
+        creation_code,
+        elem_var_name: container_elem_var_name,
+    })
 }
 
 fn create_reactive_text(expr: &RustExpression, ctx: CodegenContext) -> ElementCode {
@@ -502,27 +900,47 @@ struct ElementCode {
     elem_var_name: String,
 }
 
-fn codegen_rules(styles: &Vec<css::Property>) -> String {
+fn codegen_rules(styles: &Vec<css::Property>) -> CodegenResult {
     let mut code = "let mut __s = Style::default();".to_string();
 
     for style in styles {
+        let value = style
+            .value
+            .iter()
+            .map(|c| c.to_css())
+            .collect::<Vec<String>>();
+
+        let needed = match style.name.as_str() {
+            "border" => 2,
+            "alt" => 3,
+            _ => 1,
+        };
+
+        if value.len() < needed {
+            return Err(format!(
+                "The CSS property \"{}\" needs {} values but was given {}.",
+                style.name,
+                needed,
+                value.len()
+            ));
+        }
+
         match style.name.as_str() {
             "background-color" => {
-                let col_hex = style.value[0].trim_start_matches("#");
+                let col_hex = value[0].trim_start_matches("#");
                 code.push_str(&format!("
                                     __s.set_property(\"background-color\", StyleProperty::BackgroundColour(c(\"{col_hex}\")));
                                 "));
             }
             "border" => {
-                let split = style.value[0].split_whitespace().collect::<Vec<_>>();
-                let width = split[0];
-                let col_hex = split[1].trim_start_matches("#");
+                let width = &value[0];
+                let col_hex = value[1].trim_start_matches("#");
                 code.push_str(&format!("
                                     __s.set_property(\"border\", StyleProperty::Border {{ width: {width}, colour: c(\"{col_hex}\") }} );
                                 "));
             }
             "color" => {
-                let col_hex = style.value[0].trim_start_matches("#");
+                let col_hex = value[0].trim_start_matches("#");
                 code.push_str(&format!("
                                     __s.set_property(\"color\", StyleProperty::Color(c(\"{col_hex}\")));
                                 "));
@@ -532,42 +950,55 @@ fn codegen_rules(styles: &Vec<css::Property>) -> String {
                     "
                                     __s.set_property(\"font-size\", StyleProperty::FontSize({}));
                                 ",
-                    style.value[0]
+                    value[0]
                 ));
             }
             "font-family" => {
                 code.push_str(&format!("
                                     __s.set_property(\"font-family\", StyleProperty::FontFamily(\"{}\".to_string()));
-                                ", style.value[0]));
+                                ", value[0]));
             }
             "alt" => {
-                let width = style.value[0].clone();
-                let offset = style.value[1].clone();
-                let col_hex = style.value[2].trim_start_matches("#");
+                let width = value[0].clone();
+                let offset = value[1].clone();
+                let col_hex = value[2].trim_start_matches("#");
 
                 code.push_str(&format!("
                                     __s.set_property(\"alt\", StyleProperty::Alt(Some(({}, {}, c(\"{}\")))));
                                 ", width, offset, col_hex));
             }
-            _ => todo!(),
+            // Unsupported properties are ignored, `css_lint` warns about them.
+            _ => {}
         }
     }
 
-    code
+    Ok(code)
 }
 
 impl Element {
-    fn rs_codegen(&self, ctx: CodegenContext) -> ElementCode {
+    fn rs_codegen(&self, ctx: CodegenContext) -> Result<ElementCode, String> {
         let elem_var_name = format!("__elem{}", uid());
 
-        let (children_var_names, children_code) = codegen_children(&self.children, ctx.clone());
+        let (children_var_names, children_code) = codegen_children(&self.children, ctx.clone())?;
+
+        if self.name.is_empty() {
+            return Ok(ElementCode {
+                creation_code: format!(
+                    "
+                    {children_code}
+                    let {elem_var_name} = PhantomElement::new(vec![{children_var_names}], frame_ref.clone());
+                    "
+                ),
+                elem_var_name,
+            });
+        }
 
         let on_click = self
             .attributes
             .iter()
             .find(|a| a.name() == "onclick")
             .map(|a| match a {
-                Attribute::Reactive(ReactiveAttribute { name: _, value }) => {
+                Attribute::Reactive(ReactiveAttribute { value, .. }) => {
                     format!(
                         "
                         let bb = {elem_var_name}.borrow::<Element>().bounding_box.clone();
@@ -605,7 +1036,7 @@ impl Element {
                 ClassList::Static(classes) => {
                     classes_code = classes
                         .iter()
-                        .map(|c| format!("\"{}\".to_string()", c))
+                        .map(|c| format!("{:?}.to_string()", c))
                         .collect::<Vec<String>>()
                         .join(", ");
                 }
@@ -649,6 +1080,72 @@ impl Element {
             creation_code.push_str(&on_click);
         }
 
+        // `ref={handle}` takes a `RcRefCell<Option<Node>>` to put the element's handle in.
+        if let Some(Attribute::Reactive(ReactiveAttribute { value, .. })) =
+            self.attributes.iter().find(|a| a.name() == "ref")
+        {
+            creation_code.push_str(&format!(
+                "\n{value}.borrow_mut().replace({elem_var_name}.clone());\n"
+            ));
+        }
+
+        for directive in &self.class_directives {
+            let update_fn = format!("__class_directive_update_{}", uid());
+            let class = &directive.name;
+
+            let (expr, clones, subs) =
+                reactive_expression(&directive.value, &elem_var_name, &update_fn);
+
+            creation_code.push_str(&format!(
+                "
+                {clones}
+                let {elem_var_name}_cpy = {elem_var_name}.clone();
+                let {update_fn} = move || {{
+                    let enabled: bool = {expr};
+                    {elem_var_name}_cpy.mutate(move |e: &mut Element| {{
+                        e.class_list.retain(|c| c != \"{class}\");
+                        if enabled {{
+                            e.class_list.push(\"{class}\".to_string());
+                        }}
+                    }});
+                }};
+                {subs}
+
+                {update_fn}();"
+            ));
+        }
+
+        if let Some(directive) = self.style_directives.first() {
+            return Err(ctx.error(&format!(
+                "style:{} on <{}>, style: directives are not supported by the GL target.",
+                directive.name, self.name
+            )));
+        }
+
+        if let Some(transition) = self.transitions.first() {
+            let directive = match transition.kind {
+                TransitionKind::In => "in",
+                TransitionKind::Out => "out",
+                TransitionKind::Both => "transition",
+            };
+
+            return Err(ctx.error(&format!(
+                "{directive}:{} on <{}>, transitions are not supported by the GL target.",
+                transition.name, self.name
+            )));
+        }
+
+        if self
+            .attributes
+            .iter()
+            .any(|a| matches!(a, Attribute::Spread(_)))
+        {
+            return Err(ctx.error(&format!(
+                "Spread attributes on <{}> aren't supported by the GL target, only on components.",
+                self.name
+            )));
+        }
+
         let bound_properties = vec![
             ("x", "properties.position.x"),
             ("y", "properties.position.y"),
@@ -667,7 +1164,7 @@ impl Element {
 
                         creation_code.push_str(&format!("\n{elem_var_name}.mutate(move |e: &mut Element| {{ e.{property_name} = {value};}});\n"));
                     }
-                    Attribute::Reactive(ReactiveAttribute { name: _, value }) => {
+                    Attribute::Reactive(ReactiveAttribute { value, .. }) => {
                         let update_fn = format!("__attr_update_{}", uid());
 
                         let (expr, clones, subs) =
@@ -684,36 +1181,67 @@ impl Element {
 
                             {update_fn}();"));
                     }
+                    Attribute::Spread(_) => unreachable!(),
                 }
             }
         }
 
-        ElementCode {
+        Ok(ElementCode {
             creation_code,
             elem_var_name,
-        }
+        })
     }
 }
 
 impl Component {
-    fn rs_codegen(&self, ctx: CodegenContext) -> ElementCode {
-        let (children_var_names, children_code) = codegen_children(&self.children, ctx);
+    fn rs_codegen(&self, ctx: CodegenContext) -> Result<ElementCode, String> {
+        let ctx = CodegenContext {
+            component: self.name.clone(),
+            source: self.source.clone(),
+            ..ctx
+        };
+
+        let (children_var_names, children_code) = codegen_children(&self.children, ctx)?;
 
         let mut props = String::new();
 
-        for prop in self.props.iter() {
+        let referenced_props = referenced_props(&children_code);
+
+        for (i, prop) in self.props.iter().enumerate() {
             let (name, value) = match prop {
                 Attribute::Static(StaticAttribute { name, value }) => (
                     name,
                     value
                         .clone()
-                        .map(|v| format!("\"{}\".to_string()", v))
+                        .map(|v| format!("{:?}.to_string()", v))
                         .unwrap_or("true".to_string()),
                 ),
-                Attribute::Reactive(ReactiveAttribute { name, value }) => (name, value.clone()),
+                Attribute::Reactive(ReactiveAttribute { name, value, .. }) => (name, value.clone()),
+                Attribute::Spread(SpreadAttribute { value }) => {
+                    // Struct fields can't be enumerated, so the spread provides every prop the
+                    // component reads that isn't passed explicitly later on.
+                    let spread_var_name = format!("__spread{}", uid());
+
+                    props.push_str(&format!("let {spread_var_name} = ({value}).clone();\n"));
+
+                    for field in referenced_props.iter() {
+                        let overridden = self.props[i + 1..]
+                            .iter()
+                            .any(|p| prop_var_name(p.name()) == prop_var_name(field));
+
+                        if !overridden {
+                            let name = prop_var_name(field);
+                            props.push_str(&format!(
+                                "let {name} = {spread_var_name}.{field}.clone();\n"
+                            ));
+                        }
+                    }
+
+                    continue;
+                }
             };
 
-            let name = format!("prop_{}", name);
+            let name = prop_var_name(name);
 
             props.push_str(&format!("let {name} = ({value}).clone();\n"));
         }
@@ -725,18 +1253,46 @@ impl Component {
         "
         );
 
-        ElementCode {
+        Ok(ElementCode {
             creation_code,
             elem_var_name: children_var_names,
+        })
+    }
+}
+
+fn prop_var_name(name: &str) -> String {
+    format!("prop_{}", name.replace(['-', ':'], "_"))
+}
+
+/// The names of the props read by a component's code, without the `prop_` prefix.
+fn referenced_props(code: &str) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+
+    let mut cursor = 0;
+
+    for token in tokenize(code) {
+        let token_str = &code[cursor..cursor + token.len];
+        cursor += token.len;
+
+        if token.kind != rustc_lexer::TokenKind::Ident {
+            continue;
+        }
+
+        if let Some(name) = token_str.strip_prefix("prop_") {
+            if !name.is_empty() && !names.iter().any(|n| n == name) {
+                names.push(name.to_string());
+            }
         }
     }
+
+    names
 }
 
-fn codegen_children(children: &Vec<Node>, ctx: CodegenContext) -> (String, String) {
+fn codegen_children(children: &Vec<Node>, ctx: CodegenContext) -> Result<(String, String), String> {
     let children = children
         .iter()
         .map(|c| c.gl_codegen(ctx.clone()))
-        .collect::<Vec<_>>();
+        .collect::<Result<Vec<_>, String>>()?;
 
     let children_code = children
         .iter()
@@ -751,7 +1307,7 @@ fn codegen_children(children: &Vec<Node>, ctx: CodegenContext) -> (String, Strin
         .collect::<Vec<_>>()
         .join(", ");
 
-    (children_var_names, children_code)
+    Ok((children_var_names, children_code))
 }
 
 // let bb = __elem1.borrow::<Element>().bounding_box.clone();
@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+
+use crate::Span;
+
+/// The file of a component, which generated code is mapped back to.
+pub struct SourceFile {
+    /// What source maps call the file, relative to the generated document.
+    pub path: String,
+    pub contents: String,
+}
+
+const START_MARKER: &str = "/*@lilac:";
+const END_MARKER: &str = "/*@lilac-end*/";
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Wraps generated code in comments saying where it came from. Its lines are assumed to match the
+/// lines of the original, which holds as long as it's only had identifiers replaced.
+pub fn mark(span: &Span, code: &str) -> String {
+    if span.component.is_empty() {
+        return code.to_string();
+    }
+
+    format!(
+        "{START_MARKER}{}:{}*/{code}{END_MARKER}",
+        span.component, span.start
+    )
+}
+
+/// A comment left by `mark`, and how long it is.
+enum Marker<'a> {
+    Start {
+        component: &'a str,
+        start: usize,
+        len: usize,
+    },
+    End,
+}
+
+impl Marker<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Marker::Start { len, .. } => *len,
+            Marker::End => END_MARKER.len(),
+        }
+    }
+}
+
+/// The marker `code` starts with. Comments that only look like one, like a `/*@lilac docs */`
+/// in a user's script, aren't markers.
+fn marker_at(code: &str) -> Option<Marker<'_>> {
+    if code.starts_with(END_MARKER) {
+        return Some(Marker::End);
+    }
+
+    let marker = code.strip_prefix(START_MARKER)?;
+    let end = marker.find("*/")?;
+    let (component, start) = marker[..end].rsplit_once(':')?;
+
+    if component.is_empty() || component.contains(|c: char| c.is_whitespace() || c == '*') {
+        return None;
+    }
+
+    Some(Marker::Start {
+        component,
+        start: start.parse().ok()?,
+        len: START_MARKER.len() + end + 2,
+    })
+}
+
+/// Removes the comments left by `mark`, for builds without source maps. They're only ever in
+/// scripts, so the rest of the document is left as it is.
+pub fn strip_markers(html: &str) -> String {
+    map_scripts(html, |script| {
+        let mut output = String::with_capacity(script.len());
+        let mut rest = script;
+
+        while let Some(i) = rest.find("/*@lilac") {
+            output.push_str(&rest[..i]);
+            rest = &rest[i..];
+
+            let len = match marker_at(rest) {
+                Some(marker) => marker.len(),
+                None => {
+                    output.push_str("/*@lilac");
+                    "/*@lilac".len()
+                }
+            };
+
+            rest = &rest[len..];
+        }
+
+        output.push_str(rest);
+        output
+    })
+}
+
+/// Removes the comments left by `mark` and gives every script that had any an inline source map,
+/// so devtools show the component files rather than the generated scripts.
+pub fn attach_source_maps(html: &str, sources: &HashMap<String, SourceFile>) -> String {
+    map_scripts(html, |script| map_script(script, sources))
+}
+
+/// Replaces the body of every script in `html` with what `f` makes of it.
+fn map_scripts(html: &str, mut f: impl FnMut(&str) -> String) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = find_script(rest) {
+        let Some(open_end) = rest[start..].find('>').map(|i| start + i + 1) else {
+            break;
+        };
+        let close = rest[open_end..]
+            .find("</script>")
+            .map_or(rest.len(), |i| open_end + i);
+
+        output.push_str(&rest[..open_end]);
+        output.push_str(&f(&rest[open_end..close]));
+
+        rest = &rest[close..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+fn find_script(html: &str) -> Option<usize> {
+    html.match_indices("<script")
+        .map(|(i, _)| i)
+        .find(|&i| html[i + "<script".len()..].starts_with(|c: char| c == '>' || c.is_whitespace()))
+}
+
+/// Strips the markers from a script, followed by its source map if they pointed anywhere.
+fn map_script(script: &str, sources: &HashMap<String, SourceFile>) -> String {
+    let mut code = String::with_capacity(script.len());
+    let mut map = SourceMapBuilder::default();
+
+    // The source and line the current generated line maps to, while between markers.
+    let mut region: Option<(usize, usize)> = None;
+    let mut column = 0;
+    let mut rest = script;
+
+    while let Some(c) = rest.chars().next() {
+        match marker_at(rest) {
+            Some(Marker::Start {
+                component,
+                start,
+                len,
+            }) => {
+                rest = &rest[len..];
+
+                let Some(file) = sources.get(component) else {
+                    continue;
+                };
+
+                let (line, source_column) = line_and_column(&file.contents, start);
+                let source = map.source(&file.path);
+
+                map.segment(column, Some((source, line, source_column)));
+                region = Some((source, line));
+                continue;
+            }
+            Some(Marker::End) => {
+                if region.take().is_some() {
+                    map.segment(column, None);
+                }
+
+                rest = &rest[END_MARKER.len()..];
+                continue;
+            }
+            None => {}
+        }
+
+        code.push(c);
+        rest = &rest[c.len_utf8()..];
+
+        if c == '\n' {
+            map.new_line();
+            column = 0;
+
+            if let Some((source, line)) = &mut region {
+                *line += 1;
+                map.segment(0, Some((*source, *line, 0)));
+            }
+        } else {
+            column += c.len_utf16();
+        }
+    }
+
+    if map.sources.is_empty() {
+        return code;
+    }
+
+    let json = serde_json::json!({
+        "version": 3,
+        "sources": map.sources,
+        "names": [],
+        "mappings": map.mappings,
+    });
+
+    format!(
+        "{code}\n//# sourceMappingURL=data:application/json;charset=utf-8;base64,{}\n",
+        base64(json.to_string().as_bytes())
+    )
+}
+
+/// The zero based line and UTF-16 column of a byte offset, which is what source maps use.
+pub fn line_and_column(contents: &str, position: usize) -> (usize, usize) {
+    let mut position = position.min(contents.len());
+
+    while !contents.is_char_boundary(position) {
+        position -= 1;
+    }
+
+    let before = &contents[..position];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+    (
+        before.matches('\n').count(),
+        before[line_start..].encode_utf16().count(),
+    )
+}
+
+/// Builds the `mappings` of a source map, where every field of a segment is relative to the one
+/// before it.
+#[derive(Default)]
+struct SourceMapBuilder {
+    sources: Vec<String>,
+    mappings: String,
+    column: usize,
+    source: usize,
+    source_line: usize,
+    source_column: usize,
+}
+
+impl SourceMapBuilder {
+    fn source(&mut self, path: &str) -> usize {
+        match self.sources.iter().position(|s| s == path) {
+            Some(i) => i,
+            None => {
+                self.sources.push(path.to_string());
+                self.sources.len() - 1
+            }
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.mappings.push(';');
+        self.column = 0;
+    }
+
+    /// A segment without an original position stops the code after it from being mapped.
+    fn segment(&mut self, column: usize, original: Option<(usize, usize, usize)>) {
+        if !self.mappings.is_empty() && !self.mappings.ends_with(';') {
+            self.mappings.push(',');
+        }
+
+        vlq(column as i64 - self.column as i64, &mut self.mappings);
+        self.column = column;
+
+        if let Some((source, line, source_column)) = original {
+            vlq(source as i64 - self.source as i64, &mut self.mappings);
+            vlq(line as i64 - self.source_line as i64, &mut self.mappings);
+            vlq(
+                source_column as i64 - self.source_column as i64,
+                &mut self.mappings,
+            );
+
+            self.source = source;
+            self.source_line = line;
+            self.source_column = source_column;
+        }
+    }
+}
+
+/// Appends `value` as a base64 VLQ, the encoding of every field in `mappings`.
+pub fn vlq(value: i64, output: &mut String) {
+    let mut value = if value < 0 {
+        (-value << 1) | 1
+    } else {
+        value << 1
+    };
+
+    loop {
+        let mut digit = value & 0b11111;
+        value >>= 5;
+
+        if value > 0 {
+            digit |= 0b100000;
+        }
+
+        output.push(BASE64[digit as usize] as char);
+
+        if value == 0 {
+            break;
+        }
+    }
+}
+
+/// Standard base64 with padding, for the `data:` URL of an inline source map.
+pub fn base64(bytes: &[u8]) -> String {
+    let mut output = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(BASE64[(n >> (18 - 6 * i)) & 0b111111] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+
+    output
+}
@@ -37,13 +37,30 @@ pub fn find_and_replace_js_identifiers(
     expr
 }
 
-pub fn children_of<'a>(node: &'a mut Node) -> Option<&'a mut Vec<Node>> {
+/// Every list of child nodes directly under `node`. Most nodes have at most one, blocks with
+/// several branches have one per branch.
+pub fn children_of<'a>(node: &'a mut Node) -> Vec<&'a mut Vec<Node>> {
     match node {
-        Node::Component(c) => Some(&mut c.children),
-        Node::Element(e) => Some(&mut e.children),
-        Node::ConditionalElements { children, .. } => Some(children),
-        Node::Loop { children, .. } => Some(children),
-        _ => return None,
+        Node::Component(c) => vec![&mut c.children],
+        Node::Element(e) => vec![&mut e.children],
+        Node::ConditionalElements { children, .. } => vec![children],
+        Node::Loop {
+            children, empty, ..
+        } => vec![children, empty],
+        Node::Key { children, .. } => vec![children],
+        Node::ErrorBoundary { children, .. } => vec![children],
+        Node::Await {
+            pending,
+            then,
+            catch,
+            ..
+        } => {
+            let mut lists = vec![pending];
+            lists.extend(then.as_mut().map(|b| &mut b.children));
+            lists.extend(catch.as_mut().map(|b| &mut b.children));
+            lists
+        }
+        _ => vec![],
     }
 }
 
@@ -52,7 +69,7 @@ pub fn uid() -> String {
 }
 
 pub fn filter_whitespace_nodes(node: &mut Node) {
-    if let Some(children) = children_of(node) {
+    for children in children_of(node) {
         children.retain(|child| match child {
             Node::Text(t) => !is_all_whitespace(t),
             _ => true,
@@ -68,18 +85,30 @@ pub fn is_all_whitespace(s: &str) -> bool {
     s.chars().all(|c| c.is_whitespace())
 }
 
-pub trait StartsWithAt {
-    fn starts_with_at(&self, start_index: usize, other: &str) -> bool;
+/// Encodes text so it can be placed between HTML tags.
+pub fn encode_html_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\u{a0}', "&nbsp;")
 }
 
-impl StartsWithAt for String {
-    fn starts_with_at(&self, start_index: usize, other: &str) -> bool {
-        self.chars().skip(start_index).zip(other.chars()).all(|(a, b)| a == b)
-    }
+/// Encodes text so it can be placed in a double quoted HTML attribute value.
+pub fn encode_html_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('\u{a0}', "&nbsp;")
 }
 
-impl StartsWithAt for str {
-    fn starts_with_at(&self, start_index: usize, other: &str) -> bool {
-        self.chars().skip(start_index).zip(other.chars()).all(|(a, b)| a == b)
-    }
+/// Quotes and escapes text as a JS string literal. `<`, `>` and `&` are escaped too, so the literal
+/// can't end the inline script it's in with `</script>` or start an HTML comment, and so is the `*`
+/// of `/*`, so it can't be mistaken for a source map marker.
+pub fn js_string_literal(text: &str) -> String {
+    serde_json::to_string(text)
+        .unwrap()
+        .replace('<', "\\u003c")
+        .replace('>', "\\u003e")
+        .replace('&', "\\u0026")
+        .replace("/*", "/\\u002a")
 }
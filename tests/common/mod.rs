@@ -0,0 +1,22 @@
+//! Helpers shared by the integration tests. Each test file only uses some of them.
+#![allow(dead_code)]
+
+use lilac::{new_file, wasm_compile_};
+
+/// Compiles a single `Root` component for the browser.
+pub fn compile(root: &str) -> Result<String, String> {
+    wasm_compile_(vec![new_file("Root".to_string(), root.to_string())])
+}
+
+/// Compiles a single `Root` component for the GL target, returning the generated Rust.
+pub fn gl_compile(root: &str) -> Result<String, String> {
+    let component = lilac::parse::parse_full(root, "Root", lilac::Dialect::RsLilac)
+        .map_err(|err| err.format("Root", root))?;
+    let (styles, component) = lilac::job::collect_css(component);
+    lilac::Node::Component(component).full_gl_codegen(styles)
+}
+
+/// Like `compile`, but a release build.
+pub fn compile_release(root: &str) -> String {
+    lilac::compile_files(vec![new_file("Root".to_string(), root.to_string())], true).unwrap()
+}
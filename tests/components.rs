@@ -0,0 +1,138 @@
+mod common;
+
+use common::compile;
+use lilac::{new_file, wasm_compile_};
+
+#[test]
+fn components_get_their_own_context() {
+    let html = wasm_compile_(vec![
+        new_file(
+            "Root".to_string(),
+            "<script>setContext(\"theme\", \"dark\");</script><Child />".to_string(),
+        ),
+        new_file(
+            "Child".to_string(),
+            "<script>const theme = getContext(\"theme\");</script><p>{theme}</p>".to_string(),
+        ),
+    ])
+    .unwrap();
+    assert_eq!(html.matches("__enter_context(\"Root\");").count(), 1);
+    assert_eq!(html.matches("__enter_context(\"Child\");").count(), 1);
+    assert_eq!(html.matches("__exit_context();").count(), 2);
+}
+
+#[test]
+fn component_props_are_set_in_order() {
+    let html = wasm_compile_(vec![
+        new_file(
+            "Root".to_string(),
+            "<script>let a = {};</script><Child b=\"1\" {...a} c=\"2\" />".to_string(),
+        ),
+        new_file("Child".to_string(), "<p>{props.b}</p>".to_string()),
+    ])
+    .unwrap();
+    let b = html.find("_props.b = \"1\";").unwrap();
+    let a = html.find("Object.assign(__Child").unwrap();
+    let c = html.find("_props.c = \"2\";").unwrap();
+    assert!(b < a && a < c);
+}
+
+#[test]
+fn module_context_scripts() {
+    let html = wasm_compile_(vec![
+        new_file("Root".to_string(), "<Item /><Item />".to_string()),
+        new_file(
+            "Item".to_string(),
+            "<script context=\"module\">let count = 0;</script><script>count++;</script>"
+                .to_string(),
+        ),
+    ])
+    .unwrap();
+    assert_eq!(html.matches("let __Item_module_count = 0;").count(), 1);
+    assert_eq!(html.matches("__Item_module_count++;").count(), 2);
+}
+
+#[test]
+fn top_level_declarations() {
+    let code = "const { a, b: [c, ...d] } = x; function f(p) { let inner; var hoisted; }\n\
+                class K {} if (a) { var v; let scoped; }";
+    assert_eq!(
+        lilac::js_component_scoping::top_level_declarations(code),
+        ["a", "c", "d", "f", "K", "v"]
+    );
+}
+
+#[test]
+fn only_top_level_bindings_are_renamed() {
+    let rename = |name: &str| (name == "count").then(|| "__count".to_string());
+    let rename = |code| lilac::js_component_scoping::rename_free_identifiers(code, rename);
+    assert_eq!(rename("props.count + $count"), "props.count + $__count");
+    assert_eq!(
+        rename("({ count, n: count })"),
+        "({ count: __count, n: __count })"
+    );
+    assert_eq!(rename("(count) => count + 1"), "(count) => count + 1");
+    assert_eq!(
+        rename("function f() { let count; count }"),
+        "function f() { let count; count }"
+    );
+    assert_eq!(
+        rename("({ count() { return count } })"),
+        "({ count() { return __count } })"
+    );
+}
+
+#[test]
+fn mount_hooks_run_once_components_are_attached() {
+    let html = wasm_compile_(vec![
+        new_file("Root".to_string(), "<Item />".to_string()),
+        new_file(
+            "Item".to_string(),
+            "<script>mount(() => {});</script><p>item</p>".to_string(),
+        ),
+    ])
+    .unwrap();
+    assert!(html.contains("<p>item</p><script>__exit_context();\n__flush_mounts();</script>"));
+}
+
+#[test]
+fn element_refs() {
+    let html = compile("<script>let canvas;</script><canvas ref={canvas}></canvas>").unwrap();
+    assert!(!html.contains("setAttribute(\"ref\""));
+    assert!(html.contains("_canvas = __elem"));
+}
+
+#[test]
+fn component_events() {
+    let html = wasm_compile_(vec![
+        new_file(
+            "Root".to_string(),
+            "<Item onselect={(item) => 0} />".to_string(),
+        ),
+        new_file(
+            "Item".to_string(),
+            "<button onclick={() => dispatch(\"select\", 1)}>a</button>".to_string(),
+        ),
+    ])
+    .unwrap();
+    assert!(html.contains("_dispatch = __dispatcher({\n\"select\": ((item) => 0),\n});"));
+}
+
+#[test]
+fn handlers_for_events_never_dispatched() {
+    let parse = |name: &str, code: &str| {
+        let component = lilac::parse::parse_full(code, name, lilac::Dialect::JsLilac).unwrap();
+        (name.to_string(), component)
+    };
+    let components = std::collections::HashMap::from([
+        parse("Root", "<Item onselect={a} onremove={b} onclose={c} />"),
+        parse(
+            "Item",
+            "<script>dispatch(\"select\"); props.onclose();</script>",
+        ),
+    ]);
+    assert_eq!(
+        lilac::component_events::lint_component_events(&components),
+        ["\"Root\" Handler \"onremove\" on <Item> never runs, Item never dispatches \"remove\"."]
+    );
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use common::{compile, gl_compile};
+
+#[test]
+fn reactive_ids_are_scoped() {
+    let html = compile("<script>let n = 0; const next = () => `a${n++}`;</script><p id={next()}>x</p><style>#a0 { color: red; }</style>").unwrap();
+    // The id is evaluated once, and its scoping class follows the value it was set to.
+    assert_eq!(html.matches("next()").count(), 1);
+    assert!(html.contains(".id = val;\n"));
+    assert!(html.contains(".classList.toggle(\"id-Root-a0\", val === \"a0\");"));
+}
+
+fn lint_css(code: &str, dialect: lilac::Dialect) -> Vec<String> {
+    let component = lilac::parse::parse_full(code, "Root", dialect).unwrap();
+    let (styles, component) = lilac::job::collect_css(component);
+    lilac::css_lint::lint_component_css(&component, styles, false).1
+}
+
+#[test]
+fn unknown_css_properties() {
+    let warnings = lint_css(
+        "{#await load()}<p class=\"a\">x</p>{/await}<style>.a { colr: red; --x: 1; }</style>",
+        lilac::Dialect::JsLilac,
+    );
+    assert_eq!(
+        warnings,
+        ["\"Root\" Unknown CSS property \"colr\" in rule \".a\"."]
+    );
+}
+
+#[test]
+fn css_properties_unsupported_on_gl() {
+    let warnings = lint_css(
+        "<p class=\"a\">x</p><style>.a { color: red; margin: 0; }</style>",
+        lilac::Dialect::RsLilac,
+    );
+    assert_eq!(
+        warnings,
+        ["\"Root\" Unknown CSS property \"margin\" in rule \".a\"."]
+    );
+}
+
+#[test]
+fn css_selectors_unsupported_on_gl() {
+    let code = "<p class=\"a\"><i class=\"b\">x</i></p><style>.a .b { color: #ff0000; } .a { color: #00ff00; }</style>";
+
+    assert_eq!(
+        lint_css(code, lilac::Dialect::RsLilac),
+        ["\"Root\" Only class selectors are supported on GL, \".a .b\" is ignored."]
+    );
+
+    let rs = gl_compile(code).unwrap();
+    assert_eq!(rs.matches("stylesheet.insert(").count(), 1);
+}
+
+fn round_trip(css: &str) -> String {
+    let ss = lilac::css::parse(css).unwrap();
+    lilac::js_codegen::codegen_stylesheet(&ss)
+}
+
+#[test]
+fn css_selectors() {
+    assert_eq!(
+        round_trip("a.b#c:hover[type=\"text\"], * > p + i ~ b u { x: y }"),
+        "a.b#c:hover[type=\"text\"] {x:y;} * > p + i ~ b u {x:y;} "
+    );
+}
+
+#[test]
+fn css_nesting() {
+    assert_eq!(
+        round_trip(".a { x: y; &:hover { x: z } .b, .c { x: w } }"),
+        ".a {x:y;} .a:hover {x:z;} .a .b {x:w;} .a .c {x:w;} "
+    );
+}
+
+#[test]
+fn css_values() {
+    assert_eq!(
+        round_trip(
+            ".a { font-family: \"Fira Sans\", serif; width: calc(100% - 2px); background: url(a.png) no-repeat; --gap: 1rem 2rem; }"
+        ),
+        ".a {font-family:\"Fira Sans\", serif;width:calc(100% - 2px);background:url(a.png) no-repeat;--gap:1rem 2rem;} "
+    );
+}
+
+#[test]
+fn css_media_queries() {
+    assert_eq!(
+        round_trip(
+            "@media (width < 600px) { .a { x: y } .b { x: y } } .c { @media print { x: z } }"
+        ),
+        "@media (width < 600px) {.a {x:y;} .b {x:y;} } @media print {.c {x:z;} } "
+    );
+}
+
+#[test]
+fn css_keyframes() {
+    assert_eq!(
+        round_trip("@keyframes spin { from { rotate: 0deg } 50%, 60% { rotate: 180deg } }"),
+        "@keyframes spin {from {rotate:0deg;} 50%, 60% {rotate:180deg;} } "
+    );
+}
+
+#[test]
+fn css_font_face() {
+    assert_eq!(
+        round_trip(
+            "@font-face { font-family: A; src: url(a.woff2) } @font-face { font-family: B }"
+        ),
+        "@font-face {font-family:A;src:url(a.woff2);} @font-face {font-family:B;} "
+    );
+}
+
+#[test]
+fn css_statement_at_rules_are_errors() {
+    let err = lilac::css::parse("@import url(a.css);").unwrap_err();
+    assert_eq!(
+        err.message,
+        "@import is not supported, only at-rules with a block are"
+    );
+}
+
+#[test]
+fn gl_css_values_must_be_complete() {
+    let err = gl_compile("<p class=\"a\">x</p><style>.a { border: 1px; }</style>").unwrap_err();
+    assert_eq!(
+        err,
+        "The CSS property \"border\" needs 2 values but was given 1."
+    );
+}
+
+#[test]
+fn component_styles_with_at_rules() {
+    let html = compile(
+        "<p class=\"a\">x</p><style>@media print { .a { color: red } } @font-face { font-family: A }</style>",
+    )
+    .unwrap();
+    assert!(html.contains("@media print {.class-Root-a {color:red;} }"));
+    assert!(html.contains("@font-face {font-family:A;}"));
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use common::gl_compile;
+
+#[test]
+fn gl_style_directives_are_errors() {
+    let err = gl_compile("<p style:color={c}>x</p>").unwrap_err();
+    assert_eq!(
+        err,
+        "\"Root\" style:color on <p>, style: directives are not supported by the GL target."
+    );
+}
+
+#[test]
+fn gl_transitions_are_errors() {
+    let err = gl_compile("<p out:fade>x</p>").unwrap_err();
+    assert_eq!(
+        err,
+        "\"Root\" out:fade on <p>, transitions are not supported by the GL target."
+    );
+}
+
+#[test]
+fn gl_error_boundaries_are_errors() {
+    let err = gl_compile("<p>x</p>\n<div>\n  <ErrorBoundary><p>y</p></ErrorBoundary>\n</div>")
+        .unwrap_err();
+    assert!(err.starts_with("\"Root\" [3:3]"));
+    assert!(err.ends_with("ErrorBoundary is not supported by the GL target."));
+}
+
+#[test]
+fn gl_element_spreads_are_errors() {
+    let err = gl_compile("<p {...a}>x</p>").unwrap_err();
+    assert_eq!(
+        err,
+        "\"Root\" Spread attributes on <p> aren't supported by the GL target, only on components."
+    );
+}
+
+#[test]
+fn gl_await_blocks() {
+    let rs = gl_compile("{#await $user}<p>loading</p>{:then name}<p>{name}</p>{/await}").unwrap();
+    assert!(rs.contains("let state = __user.get_copy();"));
+    assert!(rs.contains("Some(Ok(name)) =>"));
+    assert!(rs.contains("Some(Err(_)) => {\n                    vec![]"));
+    assert!(rs.contains("PhantomElement::new(__await"));
+}
+
+#[test]
+fn gl_reactive_loop_indexes() {
+    let rs = gl_compile("{#for n, i in $lstate l}<p>{$i}: {n}</p>{/for}").unwrap();
+    assert!(rs.contains(", i: Reactive<usize>, gl: &glow::Context"));
+    assert!(rs.contains("let index = Reactive::new(index);"));
+    assert!(rs.contains("_keys_cpy.borrow().len());"));
+    assert!(rs.contains("*index <<= i;"));
+}
+
+#[test]
+fn gl_reactive_consts_are_errors() {
+    let err = gl_compile("{#if true}{@const d = $count * 2}<p>{d}</p>{/if}").unwrap_err();
+    assert_eq!(
+        err,
+        "\"Root\" {@const d = $count * 2} is reactive, which the GL target doesn't support. Use a Reactive in a script instead."
+    );
+}
+
+#[test]
+fn gl_consts_with_dollar_signs_in_strings() {
+    let rs = gl_compile("{#if true}{@const s = format!(\"${}\", x)}<p>{s}</p>{/if}").unwrap();
+    assert!(rs.contains("let s = format!(\"${}\", x);"));
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::compile_release;
+
+#[test]
+fn release_builds_keep_literals_intact() {
+    let html = compile_release(
+        "<script>
+            let __text = \"a  /* b */  __c\";
+            let __pattern = /__d\\/[a-z]+ {2}/g;
+            let __closing = \"<\\/script>\";
+            let __template = `${__text}  e`;
+        </script>
+        <p>{__text}</p>",
+    );
+
+    assert!(html.contains("=\"a  /* b */  __c\";"));
+    assert!(html.contains(" /__d\\/[a-z]+ {2}/g;"));
+    assert!(html.contains("=\"<\\/script>\";"));
+    assert!(html.contains("  e`;"));
+    assert!(!html.contains("__template"));
+    assert!(!html.contains("/*@lilac"));
+}
+
+#[test]
+fn release_builds_keep_prelude_names() {
+    let html =
+        compile_release("<script>let count = 0; window.batch = __batch;</script><p>{count}</p>");
+
+    assert!(html.contains("window.batch=__batch;"));
+    assert!(html.contains("const __enter_context=("));
+    assert!(!html.contains("__RELEASE = false"));
+}
+
+#[test]
+fn release_builds_dont_reuse_names() {
+    let html = compile_release(
+        "<script>let a = 1; let b = 2; let c = 3; let d = 4; let e = 5; let items = lstate([1, 2, 3]);</script>
+        <p>{a}{b}{c}{d}{e}</p>
+        {#for x in $lstate items}<p>{x}</p>{/for}",
+    );
+
+    // `__i` and `__j` are the loop counters, so nothing else may be shortened to them.
+    assert_eq!(html.matches("let __i=").count(), 1);
+    assert!(html.contains("for(let __i=0;__i<"));
+    assert_eq!(
+        html.matches("let __j=").count(),
+        html.matches("for(let __j=").count()
+    );
+}
+
+#[test]
+fn release_builds_strip_asserts() {
+    let html = compile_release(
+        "<script>let a = 1\nassert(a === 1)\nlet b = [a]\nassert(b.length === 1);\nif (a) assert(b)</script><p>{a}</p>",
+    );
+
+    // Only the one that's the body of the `if` is kept.
+    assert_eq!(html.matches("assert(__").count(), 1);
+    assert!(html.contains(")assert(__"));
+}
@@ -0,0 +1,80 @@
+use lilac::{new_file, wasm_compile_};
+
+#[test]
+fn modules() {
+    let html = wasm_compile_(vec![
+        new_file(
+            "stores.js".to_string(),
+            "import { double } from \"./math\";\nexport const cart = lstate([]);\nexport { double as twice };".to_string(),
+        ),
+        new_file(
+            "math.js".to_string(),
+            "export const double = (n) => n * 2;".to_string(),
+        ),
+        new_file(
+            "Root".to_string(),
+            "<script>import { cart, twice as t } from \"./stores.js\";</script><p>{t(cart.length())}</p>"
+                .to_string(),
+        ),
+    ])
+    .unwrap();
+    assert!(
+        html.find("const __module_math =").unwrap() < html.find("const __module_stores =").unwrap()
+    );
+    assert!(html.contains("const { double } = __module_math;"));
+    assert!(html.contains("return { cart, twice: double };"));
+    assert!(html.contains("} = __module_stores;"));
+    assert!(!html.contains("import"));
+}
+
+#[test]
+fn modules_have_their_own_namespace() {
+    let html = wasm_compile_(vec![
+        new_file("a.js".to_string(), "export const state = 1;".to_string()),
+        new_file("b.js".to_string(), "export const state = 2;".to_string()),
+        new_file(
+            "Root".to_string(),
+            "<script>import { state as a } from \"./a.js\"; import { state as b } from \"./b.js\";</script><p>{a + b}</p>"
+                .to_string(),
+        ),
+    ])
+    .unwrap();
+    assert!(html.contains("const __module_a = (() => {"));
+    assert!(html.contains("const __module_b = (() => {"));
+}
+
+#[test]
+fn duplicate_module_exports() {
+    let err = wasm_compile_(vec![new_file(
+        "a.js".to_string(),
+        "export const x = 1;\nconst y = 2;\nexport { y as x };".to_string(),
+    )])
+    .unwrap_err();
+    assert_eq!(err, "a.js: 'x' is exported more than once.");
+}
+
+#[test]
+fn typescript_modules_are_rejected() {
+    let err = wasm_compile_(vec![new_file(
+        "a.ts".to_string(),
+        "export const x: number = 1;".to_string(),
+    )])
+    .unwrap_err();
+    assert!(err.starts_with("a.ts: TypeScript modules aren't supported yet"));
+}
+
+#[test]
+fn missing_module_export() {
+    let err = wasm_compile_(vec![
+        new_file(
+            "stores.js".to_string(),
+            "export const cart = 1;".to_string(),
+        ),
+        new_file(
+            "Root".to_string(),
+            "<script>import { total } from \"./stores.js\";</script>".to_string(),
+        ),
+    ])
+    .unwrap_err();
+    assert!(err.contains("stores.js has no export named 'total'"));
+}
@@ -0,0 +1,242 @@
+mod common;
+
+use common::compile;
+
+#[test]
+fn data_attributes() {
+    let html = compile("<div data-id=\"12\" data-user-name=\"jo\"></div>").unwrap();
+    assert!(html.contains("<div data-id=\"12\" data-user-name=\"jo\"></div>"));
+}
+
+#[test]
+fn aria_attributes() {
+    let html = compile("<button aria-label=\"Close\" aria-hidden>x</button>").unwrap();
+    assert!(html.contains("<button aria-label=\"Close\" aria-hidden>x</button>"));
+}
+
+#[test]
+fn namespaced_attributes() {
+    let html =
+        compile("<p xml:lang=\"en\">hi</p><use xlink:href=\"#a\" stroke-width=\"2\"/>").unwrap();
+    assert!(html.contains("<p xml:lang=\"en\">hi</p>"));
+    assert!(html.contains("<use xlink:href=\"#a\" stroke-width=\"2\">"));
+}
+
+#[test]
+fn custom_elements() {
+    let html =
+        compile("<my-widget size=\"2\"><my-widget-item>a</my-widget-item></my-widget>").unwrap();
+    assert!(html.contains("<my-widget size=\"2\"><my-widget-item>a</my-widget-item></my-widget>"));
+}
+
+#[test]
+fn closing_tag_must_match_whole_name() {
+    let html = compile("<s><span>a</span></s>").unwrap();
+    assert!(html.contains("<s><span>a</span></s>"));
+}
+
+#[test]
+fn invalid_attribute_name() {
+    let err = compile("<div a<b=\"c\"></div>").unwrap_err();
+    assert!(err.contains("Invalid attribute name"));
+}
+
+#[test]
+fn single_quoted_and_unquoted_attribute_values() {
+    let html = compile("<div title='say \"hi\"' tabindex=2></div>").unwrap();
+    assert!(html.contains("<div title=\"say &quot;hi&quot;\" tabindex=\"2\"></div>"));
+}
+
+#[test]
+fn invalid_unquoted_attribute_value() {
+    let err = compile("<div title=a'b></div>").unwrap_err();
+    assert!(err.contains("Invalid attribute value"));
+}
+
+#[test]
+fn character_references() {
+    let html = compile("<p title=\"a &amp; b\">&lt;&#65;&#x42;&gt;&nbsp;&bogus;</p>").unwrap();
+    assert!(html.contains("<p title=\"a &amp; b\">&lt;AB&gt;&nbsp;&amp;bogus;</p>"));
+
+    let html =
+        compile("<p title=\"caf&eacute;\">&NotEqualTilde; &CounterClockwiseContourIntegral;</p>")
+            .unwrap();
+    assert!(html.contains("<p title=\"café\">\u{2242}\u{338} ∳</p>"));
+}
+
+#[test]
+fn non_ascii_text() {
+    let html = compile("<p>Grüße, 世界</p><span>ok</span>").unwrap();
+    assert!(html.contains("<p>Grüße, 世界</p><span>ok</span>"));
+}
+
+#[test]
+fn error_position_after_non_ascii_text() {
+    let err = compile("<p>ünïcödé</p>\n<p>é <div a<b=\"c\"></div></p>").unwrap_err();
+    assert!(err.contains("[2:"));
+}
+
+#[test]
+fn spread_attributes() {
+    let html = compile("<script>let a = {};</script><input {...a} type=\"text\">").unwrap();
+    assert!(html.contains("_attributes = __attribute_layers(__elem"));
+    assert!(html.contains("_attributes(0, val);"));
+    assert!(html.contains("_attributes(1, { \"type\": \"text\" });"));
+}
+
+#[test]
+fn spread_classes_are_merged() {
+    let html = compile(
+        "<script>let a = {};</script><p class=\"x\" {...a}>y</p><style>.x { color: red; }</style>",
+    )
+    .unwrap();
+    assert!(html.contains(".className = \"x class-Root-x\";"));
+    assert!(html.contains("_attributes(0, val);"));
+}
+
+#[test]
+fn unterminated_spread() {
+    assert!(compile("<input {...a>").is_err());
+}
+
+#[test]
+fn await_blocks() {
+    let html = compile(
+        "{#await fetch(\"/a\")}<p>loading</p>{:then res}<p>{res.status}</p>{:catch e}<p>{e}</p>{/await}",
+    )
+    .unwrap();
+    assert!(html.contains("const res = __value;"));
+    assert!(html.contains("const e = __value;"));
+}
+
+#[test]
+fn await_branch_outside_await_block() {
+    assert!(compile("<p>{:then value}</p>").is_err());
+}
+
+#[test]
+fn then_after_catch() {
+    let err = compile("{#await p}{:catch e}a{:then v}b{/await}").unwrap_err();
+    assert!(err.contains("Unexpected {:then v} in await block"));
+}
+
+#[test]
+fn key_blocks() {
+    let html = compile("<script>let id = state(1);</script>{#key $id}<p>a</p>{/key}").unwrap();
+    assert!(html.contains("key = (key) =>"));
+}
+
+#[test]
+fn loop_index_and_destructuring() {
+    let html = compile("{#for [k, v], i in [[1, 2]]}<p>{i}{k}{v}</p>{/for}").unwrap();
+    assert!(html.contains("const [k, v] = arr[__i];"));
+    assert!(html.contains("const i = position;"));
+}
+
+#[test]
+fn reactive_loop_index() {
+    let html = compile("<script>const l = lstate([]);</script>{#for x, i in $lstate l}{$i}{/for}")
+        .unwrap();
+    assert!(html.contains("const i = state(position);"));
+}
+
+#[test]
+fn invalid_loop_index() {
+    assert!(compile("{#for x, [i] in l}{/for}").is_err());
+}
+
+#[test]
+fn loop_empty_branch() {
+    let html = compile("{#for x in []}<p>{x}</p>{:empty}<p>none</p>{/for}").unwrap();
+    assert!(html.contains("if (arr.length === 0)"));
+}
+
+#[test]
+fn unexpected_loop_branch() {
+    let err = compile("{#for x in []}{:else}<p>none</p>{/for}").unwrap_err();
+    assert!(err.contains("Unexpected {:else} in for block"));
+}
+
+#[test]
+fn const_tags() {
+    let html = compile(
+        "<script>let rate = state(2);</script>
+        {#for item in [1, 2]}{@const double = item * 2}{@const cost = double * $rate}{double}{$cost}{/for}",
+    )
+    .unwrap();
+    assert!(html.contains("const double = (item * 2);"));
+    assert!(html.contains("const cost = state(undefined);"));
+}
+
+#[test]
+fn const_outside_block() {
+    let err = compile("<p>{@const a = 1}</p>").unwrap_err();
+    assert!(err.contains("{@const} is only allowed at the top of a block"));
+}
+
+#[test]
+fn blocks_render_between_anchors() {
+    let html = compile("<table>{#for row in [1, 2]}<tr><td>{row}</td></tr>{/for}</table>").unwrap();
+    assert!(html.contains("__anchors()"));
+    assert!(!html.contains("createElement(\"span\")"));
+}
+
+#[test]
+fn fragments() {
+    let html = compile("<ul><><li>a</li><li>b</li></></ul>").unwrap();
+    assert!(html.contains("<ul><li>a</li><li>b</li></ul>"));
+}
+
+#[test]
+fn unclosed_fragment() {
+    let err = compile("<><p>a</p>").unwrap_err();
+    assert!(err.contains("Could not find closing tag for fragment"));
+}
+
+#[test]
+fn transitions() {
+    let html =
+        compile("{#if true}<p transition:fade={{ duration: 200 }} out:slide>a</p>{/if}").unwrap();
+    assert!(
+        html.contains(", { fn: fade, params: ({ duration: 200 }) }, { fn: slide, params: ({}) });")
+    );
+}
+
+#[test]
+fn transition_parameters_must_be_expressions() {
+    let err = compile("<p in:fade=\"slow\">a</p>").unwrap_err();
+    assert!(err.contains("Parameters of transition fade must be an expression"));
+}
+
+#[test]
+fn error_boundaries() {
+    let html =
+        compile("<ErrorBoundary fallback=\"Something went wrong\"><p>{1 + 1}</p></ErrorBoundary>")
+            .unwrap();
+    assert!(html.contains("__error_boundary("));
+    assert!(html.contains(", (\"Something went wrong\"));"));
+    assert!(html.contains("__boundary_context("));
+}
+
+#[test]
+fn error_boundaries_only_take_a_fallback() {
+    let err = compile("<ErrorBoundary onerror={log}><p>a</p></ErrorBoundary>").unwrap_err();
+    assert!(err.contains("Invalid attribute onerror on ErrorBoundary"));
+}
+
+#[test]
+fn directives_without_values_need_identifier_names() {
+    let err = compile("<p class:is-active>x</p>").unwrap_err();
+    assert!(err.contains("Directive class:is-active needs a value"));
+
+    let html = compile("<script>let active = true;</script><p class:active>x</p>").unwrap();
+    assert!(html.contains("active"));
+}
+
+#[test]
+fn script_endings_in_block_text() {
+    let html =
+        compile("<script>let shown = true;</script>{#if shown}<i>&lt;/script&gt; &amp;</i>{/if}")
+            .unwrap();
+    assert!(html.contains("document.createTextNode(\"\\u003c/script\\u003e \\u0026\")"));
+}
@@ -0,0 +1,247 @@
+mod common;
+
+use common::{compile, compile_release};
+
+#[test]
+fn source_maps() {
+    let html = compile("<script>\nconst a = 1;\n</script><p title={a}>{a}</p>").unwrap();
+    assert!(!html.contains("/*@lilac"));
+    assert!(html.contains("\n//# sourceMappingURL=data:application/json;charset=utf-8;base64,"));
+    assert!(html.contains("const __Root_"));
+}
+
+/// Every mapped segment of the inline source maps in `html`, as the rest of the generated line from
+/// the segment on and the zero based source line and column it points to.
+fn source_mappings(html: &str) -> Vec<(String, String, usize, usize)> {
+    const PREFIX: &str = "\n//# sourceMappingURL=data:application/json;charset=utf-8;base64,";
+
+    let mut segments = vec![];
+
+    for script in html.split("<script>").skip(1) {
+        let script = &script[..script.find("</script>").unwrap()];
+        let Some(i) = script.find(PREFIX) else {
+            continue;
+        };
+
+        let map = decode_base64(script[i + PREFIX.len()..].trim());
+        let map: serde_json::Value = serde_json::from_slice(&map).unwrap();
+        let sources = map["sources"].as_array().unwrap();
+        let lines: Vec<&str> = script[..i].split('\n').collect();
+
+        let (mut source, mut line, mut column) = (0, 0, 0);
+
+        for (generated_line, segments_of_line) in
+            map["mappings"].as_str().unwrap().split(';').enumerate()
+        {
+            let mut generated_column = 0;
+
+            for segment in segments_of_line.split(',').filter(|s| !s.is_empty()) {
+                let fields = decode_vlqs(segment);
+                generated_column += fields[0];
+
+                if fields.len() == 4 {
+                    source += fields[1];
+                    line += fields[2];
+                    column += fields[3];
+
+                    segments.push((
+                        lines[generated_line][generated_column as usize..].to_string(),
+                        sources[source as usize].as_str().unwrap().to_string(),
+                        line as usize,
+                        column as usize,
+                    ));
+                }
+            }
+        }
+    }
+
+    segments
+}
+
+fn decode_vlqs(segment: &str) -> Vec<i64> {
+    let mut values = vec![];
+    let (mut value, mut shift) = (0, 0);
+
+    for digit in decode_base64_digits(segment) {
+        value += ((digit & 0b11111) as i64) << shift;
+
+        if digit & 0b100000 != 0 {
+            shift += 5;
+        } else {
+            values.push(if value & 1 == 1 {
+                -(value >> 1)
+            } else {
+                value >> 1
+            });
+            (value, shift) = (0, 0);
+        }
+    }
+
+    values
+}
+
+fn decode_base64_digits(text: &str) -> Vec<u8> {
+    const BASE64: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    text.chars()
+        .map(|c| BASE64.find(c).unwrap() as u8)
+        .collect()
+}
+
+fn decode_base64(text: &str) -> Vec<u8> {
+    let digits = decode_base64_digits(text.trim_end_matches('='));
+    let mut bytes = vec![];
+
+    for chunk in digits.chunks(4) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &d)| n | (d as u32) << (18 - 6 * i));
+
+        bytes.extend(&n.to_be_bytes()[1..chunk.len()]);
+    }
+
+    bytes
+}
+
+/// `code` with the `__Root_<id>_` prefix taken off every identifier of the root component.
+fn unscoped(code: &str) -> String {
+    let mut output = String::new();
+    let mut rest = code;
+
+    while let Some(i) = rest.find("__Root_") {
+        output.push_str(&rest[..i]);
+        rest = rest[i + "__Root_".len()..].trim_start_matches(|c: char| c.is_ascii_digit());
+        rest = rest.strip_prefix('_').unwrap_or(rest);
+    }
+
+    output.push_str(rest);
+    output
+}
+
+#[test]
+fn source_maps_point_at_the_original() {
+    let source = "<script>\nlet a = 1;\n</script>\n<p title={a + 1}>\n  {a * 2}\n</p>";
+    let html = compile(source).unwrap();
+    let lines: Vec<&str> = source.lines().collect();
+
+    // Renamed identifiers are put back, so the generated code can be compared to the source.
+    let mappings: Vec<_> = source_mappings(&html)
+        .into_iter()
+        .map(|(generated, file, line, column)| (unscoped(&generated), file, line, column))
+        .collect();
+
+    // The script's line, the attribute and the reactive text, each mapped to where it's written.
+    for (generated, line, column) in [("let a = 1;", 1, 0), ("a + 1);", 3, 10), ("a * 2);", 4, 3)] {
+        assert!(
+            mappings
+                .iter()
+                .any(|(g, file, l, c)| g.starts_with(generated)
+                    && file == "Root.lilac"
+                    && (*l, *c) == (line, column)),
+            "{generated} isn't mapped to {line}:{column} in {mappings:?}"
+        );
+        assert!(lines[line][column..].starts_with(&generated[..5]));
+    }
+}
+
+#[test]
+fn source_map_vlqs() {
+    for (value, encoded) in [
+        (0, "A"),
+        (1, "C"),
+        (-1, "D"),
+        (15, "e"),
+        (16, "gB"),
+        (-16, "hB"),
+        (1000, "w+B"),
+    ] {
+        let mut output = String::new();
+        lilac::source_map::vlq(value, &mut output);
+        assert_eq!(output, encoded);
+        assert_eq!(decode_vlqs(&output), vec![value]);
+    }
+}
+
+#[test]
+fn source_map_base64() {
+    use lilac::source_map::base64;
+
+    assert_eq!(base64(b""), "");
+    assert_eq!(base64(b"M"), "TQ==");
+    assert_eq!(base64(b"Ma"), "TWE=");
+    assert_eq!(base64(b"Man"), "TWFu");
+    assert_eq!(
+        decode_base64(&base64("{\"é\":[]}".as_bytes())),
+        "{\"é\":[]}".as_bytes()
+    );
+}
+
+#[test]
+fn source_map_lines_and_columns() {
+    use lilac::source_map::line_and_column;
+
+    let contents = "ab\ncafé😀x\n";
+    assert_eq!(line_and_column(contents, 0), (0, 0));
+    assert_eq!(line_and_column(contents, 3), (1, 0));
+    // UTF-16 columns, so the emoji counts twice, and offsets inside a character round down.
+    assert_eq!(
+        line_and_column(contents, contents.find('x').unwrap()),
+        (1, 6)
+    );
+    assert_eq!(
+        line_and_column(contents, contents.find('😀').unwrap() + 1),
+        (1, 4)
+    );
+    assert_eq!(line_and_column(contents, 100), (2, 0));
+}
+
+#[test]
+fn source_map_markers() {
+    use lilac::source_map::{attach_source_maps, strip_markers, SourceFile};
+
+    let marked = "<script>x = /*@lilac:Root:9*/a + 1/*@lilac-end*/; /*@lilac docs */\n</script><p>/*@lilac:Root:9*/b/*@lilac-end*/</p>";
+
+    // Only the markers in scripts are `mark`'s, and comments that merely look like one are kept.
+    assert_eq!(
+        strip_markers(marked),
+        "<script>x = a + 1; /*@lilac docs */\n</script><p>/*@lilac:Root:9*/b/*@lilac-end*/</p>"
+    );
+
+    let sources = std::collections::HashMap::from([(
+        "Root".to_string(),
+        SourceFile {
+            path: "Root.lilac".to_string(),
+            contents: "<p title={a + 1}>x</p>".to_string(),
+        },
+    )]);
+    let html = attach_source_maps(marked, &sources);
+
+    assert!(html.starts_with("<script>x = a + 1; /*@lilac docs */\n\n//# sourceMappingURL="));
+    assert!(html.ends_with("</script><p>/*@lilac:Root:9*/b/*@lilac-end*/</p>"));
+    assert_eq!(
+        source_mappings(&html),
+        vec![(
+            "a + 1; /*@lilac docs */".to_string(),
+            "Root.lilac".to_string(),
+            0,
+            9
+        )]
+    );
+
+    // Markers of components without a source are dropped without a map.
+    assert_eq!(
+        attach_source_maps(marked, &Default::default()),
+        strip_markers(marked)
+    );
+}
+
+#[test]
+fn source_map_markers_in_user_content() {
+    let source = "<script>let shown = true;</script><p>see /*@lilac docs */ here</p>{#if shown}<i>/*@lilac-end*/ b</i>{/if}";
+
+    for html in [compile(source).unwrap(), compile_release(source)] {
+        assert!(html.contains("<p>see /*@lilac docs */ here</p>"));
+        assert!(html.contains("\"/\\u002a@lilac-end*/ b\""));
+    }
+}